@@ -1,28 +1,84 @@
-use std::str::FromStr;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    str::FromStr,
+    sync::{atomic::Ordering, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
 
-use alex::{position::Position, search::search, types::move_to_mfen};
+use alex::{
+    book::Book,
+    eval::EvalParams,
+    mate::{mate_search, MateLimits, MateResult},
+    options::{
+        EngineOptions, OPTIONS, OPTION_BOOK_FILE, OPTION_DEBUG_LOG_FILE, OPTION_EVAL_FILE,
+        OPTION_HASH,
+    },
+    perft,
+    position::Position,
+    search::{search, SearchLimits, SearchOptions, Signals},
+    tt::TranspositionTable,
+    types::{move_to_mfen, Side},
+};
 use nom::{
     branch::alt,
-    bytes::complete::{is_a, tag},
-    character::complete::{space0, space1, u32},
-    combinator::{opt, value},
-    multi::separated_list0,
+    bytes::complete::{is_a, tag, take_until},
+    character::complete::{space0, space1, u32, u64},
+    combinator::{eof, map, opt, rest, value},
+    multi::{many0, separated_list0},
     number::complete::double,
+    sequence::{preceded, terminated},
     IResult,
 };
 
+/// File to log the communication with the GUI.
+static DEBUG_LOG: Mutex<Option<File>> = Mutex::new(None);
+
+fn log(prefix: &str, line: &str) {
+    if let Some(file) = DEBUG_LOG.lock().unwrap().as_mut() {
+        // A failure of logging must not stop the engine.
+        let _ = writeln!(file, "{} {}", prefix, line);
+    }
+}
+
+/// Prints a line to the GUI and the debug log.
+macro_rules! output {
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        println!("{}", line);
+        log(">", &line);
+    }};
+}
+
 enum Command {
-    UMI,
+    Umi,
     IsReady,
+    SetOption(String, Option<String>),
     NewGame,
     Position(String, Vec<String>),
-    Go(f64),
-    Perft(usize, bool),
+    Go(SearchLimits, bool),
+    GoMate(MateLimits),
+    Stop,
+    PonderHit,
+    Quit,
+    Perft(usize, PerftMode),
+    Result,
+}
+
+/// What `perft` prints besides the total.
+#[derive(Clone, Copy)]
+enum PerftMode {
+    Total,
+    /// Counts after each move.
+    Divide,
+    /// Counts of the leaf moves by their kind.
+    Stats,
 }
 
 fn umi(s: &str) -> IResult<&str, Command> {
     let (s, _) = tag("umi")(s)?;
-    Ok((s, Command::UMI))
+    Ok((s, Command::Umi))
 }
 
 fn isready(s: &str) -> IResult<&str, Command> {
@@ -30,6 +86,20 @@ fn isready(s: &str) -> IResult<&str, Command> {
     Ok((s, Command::IsReady))
 }
 
+fn setoption(s: &str) -> IResult<&str, Command> {
+    let (s, _) = tag("setoption")(s)?;
+    let (s, _) = space1(s)?;
+    let (s, _) = tag("name")(s)?;
+    let (s, _) = space1(s)?;
+    let (s, name) = alt((take_until(" value"), rest))(s)?;
+    let (s, value) = opt(preceded(tag(" value"), preceded(space0, rest)))(s)?;
+    let value = value.filter(|v: &&str| !v.is_empty());
+    Ok((
+        s,
+        Command::SetOption(name.trim().to_string(), value.map(|v| v.to_string())),
+    ))
+}
+
 fn new_game(s: &str) -> IResult<&str, Command> {
     let (s, _) = tag("uminewgame")(s)?;
     Ok((s, Command::NewGame))
@@ -71,71 +141,445 @@ fn position(s: &str) -> IResult<&str, Command> {
     ))
 }
 
+/// Parameter of the go command.
+#[derive(Clone)]
+enum GoParam {
+    Ponder,
+    Infinite,
+    Time(Side, u64),
+    Inc(Side, u64),
+    Byoyomi(u64),
+    Depth(u64),
+    Nodes(u64),
+    MoveTime(u64),
+}
+
+fn go_param(s: &str) -> IResult<&str, GoParam> {
+    fn number<'a>(
+        name: &'static str,
+        f: impl Fn(u64) -> GoParam,
+    ) -> impl FnMut(&'a str) -> IResult<&'a str, GoParam> {
+        map(preceded(tag(name), preceded(space1, u64)), f)
+    }
+    alt((
+        value(GoParam::Ponder, tag("ponder")),
+        value(GoParam::Infinite, tag("infinite")),
+        number("btime", |t| GoParam::Time(Side::Black, t)),
+        number("wtime", |t| GoParam::Time(Side::White, t)),
+        number("binc", |t| GoParam::Inc(Side::Black, t)),
+        number("winc", |t| GoParam::Inc(Side::White, t)),
+        number("byoyomi", GoParam::Byoyomi),
+        number("depth", GoParam::Depth),
+        number("nodes", GoParam::Nodes),
+        number("movetime", GoParam::MoveTime),
+    ))(s)
+}
+
 fn go(s: &str) -> IResult<&str, Command> {
+    let (s, _) = tag("go")(s)?;
+    // `go <seconds>` is kept for compatibility.
+    if let Ok((s, time)) = terminated(preceded(space1::<&str, ()>, double), eof)(s) {
+        if !time.is_finite() || time < 0.0 {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                s,
+                nom::error::ErrorKind::Float,
+            )));
+        }
+        return Ok((
+            s,
+            Command::Go(SearchLimits::movetime((time * 1000.0) as u64), false),
+        ));
+    }
+    let (s, params) = many0(preceded(space1, go_param))(s)?;
+    let (s, _) = space0(s)?;
+    let (s, _) = eof(s)?;
+    let mut limits = SearchLimits::default();
+    let mut ponder = false;
+    for param in params {
+        match param {
+            GoParam::Ponder => ponder = true,
+            GoParam::Infinite => limits.infinite = true,
+            GoParam::Time(side, t) => limits.time[side as usize] = Some(t),
+            GoParam::Inc(side, t) => limits.inc[side as usize] = t,
+            GoParam::Byoyomi(t) => limits.byoyomi = t,
+            GoParam::Depth(d) => limits.depth = Some(d as usize),
+            GoParam::Nodes(n) => limits.nodes = Some(n),
+            GoParam::MoveTime(t) => limits.movetime = Some(t),
+        }
+    }
+    Ok((s, Command::Go(limits, ponder)))
+}
+
+/// `go mate <milliseconds>` or `go mate infinite` searches for a mate by continuous checks.
+fn go_mate(s: &str) -> IResult<&str, Command> {
     let (s, _) = tag("go")(s)?;
     let (s, _) = space1(s)?;
-    let (s, time) = double(s)?;
-    Ok((s, Command::Go(time)))
+    let (s, _) = tag("mate")(s)?;
+    let (s, time) = opt(preceded(
+        space1,
+        alt((value(None, tag("infinite")), map(u64, Some))),
+    ))(s)?;
+    let (s, _) = space0(s)?;
+    let (s, _) = eof(s)?;
+    Ok((
+        s,
+        Command::GoMate(MateLimits {
+            time: time.flatten(),
+            ..Default::default()
+        }),
+    ))
+}
+
+fn stop(s: &str) -> IResult<&str, Command> {
+    let (s, _) = tag("stop")(s)?;
+    Ok((s, Command::Stop))
+}
+
+fn ponderhit(s: &str) -> IResult<&str, Command> {
+    let (s, _) = tag("ponderhit")(s)?;
+    Ok((s, Command::PonderHit))
+}
+
+fn quit(s: &str) -> IResult<&str, Command> {
+    let (s, _) = tag("quit")(s)?;
+    Ok((s, Command::Quit))
 }
 
 fn perft(s: &str) -> IResult<&str, Command> {
     let (s, _) = tag("perft")(s)?;
     let (s, _) = space1(s)?;
     let (s, depth) = u32(s)?;
+    let (s, mode) = opt(preceded(
+        space1,
+        alt((
+            value(PerftMode::Divide, tag("divide")),
+            value(PerftMode::Stats, tag("stats")),
+        )),
+    ))(s)?;
     let (s, _) = space0(s)?;
-    let (s, debug) = opt(tag("debug"))(s)?;
-    Ok((s, Command::Perft(depth as usize, debug.is_some())))
+    let (s, _) = eof(s)?;
+    Ok((
+        s,
+        Command::Perft(depth as usize, mode.unwrap_or(PerftMode::Total)),
+    ))
+}
+
+fn result(s: &str) -> IResult<&str, Command> {
+    let (s, _) = tag("result")(s)?;
+    Ok((s, Command::Result))
 }
 
 fn command(s: &str) -> IResult<&str, Command> {
-    alt((umi, isready, new_game, position, go, perft))(s)
+    alt((
+        umi, isready, setoption, new_game, position, go_mate, go, stop, ponderhit, quit, perft,
+        result,
+    ))(s)
+}
+
+/// Stops the running search and waits for its bestmove.
+fn stop_search(signals: &Signals, worker: &mut Option<JoinHandle<()>>) {
+    if let Some(handle) = worker.take() {
+        signals.stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+}
+
+/// Starts a search on a worker thread, which prints bestmove exactly once.
+fn start_search(
+    mut position: Position,
+    limits: SearchLimits,
+    options: SearchOptions,
+    print_ponder: bool,
+    tt: Arc<TranspositionTable>,
+    signals: Arc<Signals>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        // The rank of the line is shown only in the MultiPV mode.
+        let show_multipv = options.multi_pv > 1;
+        let info = search(
+            &mut position,
+            &limits,
+            &tt,
+            &options,
+            &signals,
+            &mut |info| {
+                let multipv = if show_multipv {
+                    format!(" multipv {}", info.multipv)
+                } else {
+                    String::new()
+                };
+                output!(
+                    "info depth {}{} seldepth {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+                    info.depth,
+                    multipv,
+                    info.seldepth,
+                    info.score,
+                    info.nodes,
+                    info.nps,
+                    info.time,
+                    info.hashfull,
+                    info.pv.join(" ")
+                );
+            },
+        );
+        // While pondering or searching infinitely, bestmove waits for stop or ponderhit.
+        while (limits.infinite || signals.ponder.load(Ordering::Relaxed))
+            && !signals.stop.load(Ordering::Relaxed)
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+        if let Some(info) = info {
+            let ponder = info
+                .root_moves
+                .iter()
+                .find(|(mv, _, _)| *mv == info.mv)
+                .and_then(|(_, _, line)| line.first());
+            if let Some(ponder) = ponder.filter(|_| print_ponder) {
+                output!(
+                    "bestmove {} ponder {}",
+                    move_to_mfen(info.mv, position.side),
+                    move_to_mfen(*ponder, !position.side)
+                );
+            } else {
+                output!("bestmove {}", move_to_mfen(info.mv, position.side));
+            }
+        } else {
+            output!("info string result {}", position.game_result(None));
+            output!("bestmove resign");
+        }
+    })
+}
+
+/// Starts a mate search on a worker thread, which prints checkmate exactly once.
+fn start_mate_search(
+    mut position: Position,
+    limits: MateLimits,
+    signals: Arc<Signals>,
+) -> JoinHandle<()> {
+    thread::spawn(
+        move || match mate_search(&mut position, &limits, &signals) {
+            MateResult::Mate(moves) => {
+                let mut side = position.side;
+                let mut mfens = Vec::new();
+                for mv in moves {
+                    mfens.push(move_to_mfen(mv, side));
+                    side = !side;
+                }
+                output!("checkmate {}", mfens.join(" "));
+            }
+            MateResult::NoMate => output!("checkmate nomate"),
+            MateResult::Unknown => output!("checkmate timeout"),
+        },
+    )
+}
+
+/// Opens the debug log, or closes it if `path` is empty.
+fn open_debug_log(path: &str) -> Result<(), String> {
+    let file = if path.is_empty() {
+        None
+    } else {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        Some(file)
+    };
+    *DEBUG_LOG.lock().unwrap() = file;
+    Ok(())
 }
 
 fn main() {
     let mut position = None;
+    let mut options = EngineOptions::default();
+    let mut eval_params = EvalParams::default();
+    let mut book = None;
+    let mut tt = Arc::new(TranspositionTable::new(options.hash));
+    let signals = Arc::new(Signals::default());
+    let mut worker = None;
+    let mut infinite = false;
     loop {
         let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
+        if std::io::stdin().read_line(&mut input).unwrap() == 0 {
+            // End of input. A limited search is allowed to finish.
+            signals.ponder.store(false, Ordering::Relaxed);
+            if infinite {
+                stop_search(&signals, &mut worker);
+            } else if let Some(handle) = worker.take() {
+                handle.join().unwrap();
+            }
+            break;
+        }
         let input = input.trim();
+        log("<", input);
         if let Ok((_, cmd)) = command(input) {
             match cmd {
-                Command::UMI => println!("umiok"),
-                Command::IsReady => println!("readyok"),
-                Command::NewGame => {}
+                Command::Umi => {
+                    output!("id name alex {}", env!("CARGO_PKG_VERSION"));
+                    output!("id author KEY271");
+                    for def in OPTIONS {
+                        output!("{}", def);
+                    }
+                    output!("umiok");
+                }
+                Command::IsReady => {
+                    alex::eval::init(&eval_params);
+                    output!("readyok");
+                }
+                Command::SetOption(name, value) => {
+                    // The options are kept unchanged if the new value cannot be applied.
+                    let mut new_options = options.clone();
+                    let result = new_options
+                        .set(&name, value.as_deref())
+                        .map_err(|e| e.to_string())
+                        .and_then(|name| match name {
+                            OPTION_HASH => {
+                                stop_search(&signals, &mut worker);
+                                tt = Arc::new(TranspositionTable::new(new_options.hash));
+                                Ok(())
+                            }
+                            OPTION_EVAL_FILE if new_options.eval_file.is_empty() => {
+                                eval_params = EvalParams::default();
+                                Ok(())
+                            }
+                            OPTION_EVAL_FILE => {
+                                eval_params = EvalParams::load(&new_options.eval_file)
+                                    .map_err(|e| format!("{}: {}", new_options.eval_file, e))?;
+                                Ok(())
+                            }
+                            OPTION_BOOK_FILE if new_options.book_file.is_empty() => {
+                                book = None;
+                                Ok(())
+                            }
+                            OPTION_BOOK_FILE => {
+                                let loaded = Book::load(&new_options.book_file)
+                                    .map_err(|e| format!("{}: {}", new_options.book_file, e))?;
+                                book = Some(loaded);
+                                Ok(())
+                            }
+                            OPTION_DEBUG_LOG_FILE => open_debug_log(&new_options.debug_log_file),
+                            _ => Ok(()),
+                        });
+                    match result {
+                        Ok(()) => options = new_options,
+                        Err(e) => output!("info string error: {}", e),
+                    }
+                }
+                Command::NewGame => {
+                    stop_search(&signals, &mut worker);
+                    tt.clear();
+                }
                 Command::Position(mfen, moves) => {
-                    let mut temp = Position::from_str(&mfen).unwrap();
+                    let mut temp = match Position::from_str(&mfen) {
+                        Ok(temp) => temp,
+                        Err(e) => {
+                            output!("info string error: {}", e);
+                            position = None;
+                            continue;
+                        }
+                    };
+                    // The whole command is rejected if any move is illegal.
+                    let mut legal = true;
                     for m in moves {
-                        let mv = temp.read_move(m.clone()).unwrap();
-                        if temp.is_pseudo_legal(mv) {
-                            temp.do_move(mv, None);
-                        } else {
-                            println!("illegal move: {}", m);
-                            break;
+                        match temp.read_move(m) {
+                            Ok(mv) => temp.do_move(mv, None),
+                            Err(e) => {
+                                output!("info string error: {}", e);
+                                legal = false;
+                                break;
+                            }
                         }
                     }
-                    position = Some(temp);
+                    position = legal.then_some(temp);
                 }
-                Command::Go(time) => {
-                    if let Some(position) = &mut position {
-                        let mv = search(position, time);
-                        if let Some(info) = mv {
-                            println!("info depth {}", info.depth);
-                            println!("info score cp {}", info.value);
-                            println!("bestmove {}", move_to_mfen(info.mv, position.side));
-                        } else {
-                            println!("bestmove resign");
+                Command::Go(limits, ponder) => {
+                    stop_search(&signals, &mut worker);
+                    if let Some(position) = &position {
+                        // A book move is played at once unless the GUI waits for bestmove.
+                        let book_move = book
+                            .as_ref()
+                            .and_then(|book| book.probe(position))
+                            .filter(|&mv| position.is_pseudo_legal(mv) && position.is_legal(mv));
+                        if let Some(mv) = book_move.filter(|_| !ponder && !limits.infinite) {
+                            output!("info string book");
+                            output!("bestmove {}", move_to_mfen(mv, position.side));
+                            continue;
                         }
+                        signals.stop.store(false, Ordering::Relaxed);
+                        signals.ponder.store(ponder, Ordering::Relaxed);
+                        infinite = limits.infinite;
+                        worker = Some(start_search(
+                            position.clone(),
+                            limits,
+                            SearchOptions {
+                                eval_params,
+                                ..options.search_options()
+                            },
+                            options.ponder,
+                            tt.clone(),
+                            signals.clone(),
+                        ));
+                    } else {
+                        // The GUI waits for bestmove even without a position.
+                        output!("info string error: no position");
+                        output!("bestmove resign");
+                    }
+                }
+                Command::GoMate(limits) => {
+                    stop_search(&signals, &mut worker);
+                    if let Some(position) = &position {
+                        signals.stop.store(false, Ordering::Relaxed);
+                        signals.ponder.store(false, Ordering::Relaxed);
+                        infinite = limits.time.is_none();
+                        worker = Some(start_mate_search(position.clone(), limits, signals.clone()));
                     }
                 }
-                Command::Perft(depth, debug) => {
+                Command::Stop => stop_search(&signals, &mut worker),
+                Command::PonderHit => signals.ponder.store(false, Ordering::Relaxed),
+                Command::Quit => {
+                    stop_search(&signals, &mut worker);
+                    break;
+                }
+                Command::Perft(depth, mode) => {
+                    stop_search(&signals, &mut worker);
                     if let Some(position) = &mut position {
-                        let nodes = alex::perft::perft(position, depth, debug);
-                        println!("nodes: {}", nodes);
+                        match mode {
+                            PerftMode::Total => {
+                                output!("nodes: {}", perft::perft(position, depth));
+                            }
+                            PerftMode::Divide => {
+                                let mut nodes = 0;
+                                for (mv, count) in perft::perft_divide(position, depth) {
+                                    output!("{}: {}", move_to_mfen(mv, position.side), count);
+                                    nodes += count;
+                                }
+                                output!("nodes: {}", nodes);
+                            }
+                            PerftMode::Stats => {
+                                let stats = perft::perft_stats(position, depth);
+                                output!(
+                                    "nodes: {} captures: {} drops: {} shoots: {} returns: {} supplies: {} demises: {} checks: {}",
+                                    stats.nodes,
+                                    stats.captures,
+                                    stats.drops,
+                                    stats.shoots,
+                                    stats.returns,
+                                    stats.supplies,
+                                    stats.demises,
+                                    stats.checks
+                                );
+                            }
+                        }
+                    }
+                }
+                Command::Result => {
+                    if let Some(position) = &position {
+                        output!("result {}", position.game_result(None));
                     }
                 }
             }
         } else {
-            println!("unknown command: {}", input);
+            output!("unknown command: {}", input);
         };
     }
 }
@@ -3,18 +3,77 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use axum::{extract::State, Json};
+use axum::{extract::State, http::StatusCode, Json};
 use serde::{Deserialize, Serialize};
 
 use alex::{
+    game::GameResult,
+    mate::{mate_search, MateLimits, MateResult},
     position::Position,
-    search::search,
-    types::{move_to_mfen, Value},
+    search::{search, RepetitionRule, SearchLimits, SearchOptions, Signals},
+    tt::TranspositionTable,
+    types::{move_to_mfen, Move, Side, Value},
 };
 
-pub async fn get_board(State(position): State<Arc<Mutex<Position>>>) -> String {
+pub struct AppState {
+    pub position: Mutex<Position>,
+    pub tt: TranspositionTable,
+    /// Count of search threads.
+    pub threads: usize,
+    pub rule: RepetitionRule,
+}
+
+/// Rejects a request with an invalid MFEN or move.
+fn bad_request(e: impl std::error::Error) -> (StatusCode, String) {
+    println!("bad request: {}", e);
+    (StatusCode::BAD_REQUEST, e.to_string())
+}
+
+pub async fn get_board(State(state): State<Arc<AppState>>) -> String {
     println!("GET: /api/board");
-    position.lock().unwrap().to_string()
+    state.position.lock().unwrap().to_string()
+}
+
+#[derive(Serialize)]
+pub struct ResultJson {
+    /// "ongoing", "win" or "draw".
+    result: String,
+    winner: Option<String>,
+    reason: Option<String>,
+}
+
+impl From<GameResult> for ResultJson {
+    fn from(result: GameResult) -> Self {
+        match result {
+            GameResult::Ongoing => ResultJson {
+                result: "ongoing".to_string(),
+                winner: None,
+                reason: None,
+            },
+            GameResult::Win(side, reason) => ResultJson {
+                result: "win".to_string(),
+                winner: Some(
+                    if side == Side::Black {
+                        "black"
+                    } else {
+                        "white"
+                    }
+                    .to_string(),
+                ),
+                reason: Some(reason.to_string()),
+            },
+            GameResult::Draw(reason) => ResultJson {
+                result: "draw".to_string(),
+                winner: None,
+                reason: Some(reason.to_string()),
+            },
+        }
+    }
+}
+
+pub async fn get_result(State(state): State<Arc<AppState>>) -> Json<ResultJson> {
+    println!("GET: /api/result");
+    Json(state.position.lock().unwrap().game_result(None).into())
 }
 
 #[derive(Deserialize)]
@@ -22,10 +81,14 @@ pub struct BoardMfen {
     mfen: String,
 }
 
-pub async fn post_board(State(position): State<Arc<Mutex<Position>>>, Json(mfen): Json<BoardMfen>) {
+pub async fn post_board(
+    State(state): State<Arc<AppState>>,
+    Json(mfen): Json<BoardMfen>,
+) -> Result<Json<ResultJson>, (StatusCode, String)> {
     println!("POST: /api/board; {}", mfen.mfen);
-    let mut position = position.lock().unwrap();
-    *position = Position::from_str(&mfen.mfen).unwrap();
+    let mut position = state.position.lock().unwrap();
+    *position = Position::from_str(&mfen.mfen).map_err(bad_request)?;
+    Ok(Json(position.game_result(None).into()))
 }
 
 #[derive(Deserialize)]
@@ -33,24 +96,32 @@ pub struct MoveMfen {
     mfen: String,
 }
 
-pub async fn post_move(State(position): State<Arc<Mutex<Position>>>, Json(m): Json<MoveMfen>) {
+pub async fn post_move(
+    State(state): State<Arc<AppState>>,
+    Json(m): Json<MoveMfen>,
+) -> Result<Json<ResultJson>, (StatusCode, String)> {
     println!("POST: /api/move; {}", m.mfen);
-    let mut position = position.lock().unwrap();
-    if let Ok(mv) = position.read_move(m.mfen.clone()) {
-        if position.is_pseudo_legal(mv) {
-            position.do_move(mv, None);
-        } else {
-            println!("illegal move: {}", m.mfen);
-        }
-    } else {
-        println!("unknown move: {}", m.mfen);
-    }
+    let mut position = state.position.lock().unwrap();
+    let mv = position.read_move(m.mfen).map_err(bad_request)?;
+    position.do_move(mv, None);
+    Ok(Json(position.game_result(None).into()))
 }
 
 #[derive(Deserialize)]
 pub struct Go {
     mfen: String,
     time: f64,
+    /// Count of the best lines with exact values. 1 if omitted.
+    multipv: Option<usize>,
+}
+
+/// Line starting with a root move.
+#[derive(Serialize)]
+pub struct PvLine {
+    mfen: String,
+    value: Value,
+    /// Moves after the root move.
+    pv: Vec<String>,
 }
 
 #[derive(Serialize)]
@@ -60,36 +131,150 @@ pub struct Bestmove {
     value: Value,
     root_moves: Vec<(String, Value)>,
     pv: Vec<String>,
+    /// Best lines ordered by the values, as many as `multipv`.
+    lines: Vec<PvLine>,
+}
+
+/// Converts the moves played alternately from `side` to MFEN.
+fn moves_to_mfen(moves: &[Move], side: Side) -> Vec<String> {
+    let mut side = side;
+    let mut mfens = Vec::new();
+    for &mv in moves {
+        mfens.push(move_to_mfen(mv, side));
+        side = !side;
+    }
+    mfens
 }
 
-pub async fn post_bestmove(Json(bmv): Json<Go>) -> Json<Bestmove> {
+pub async fn post_bestmove(
+    State(state): State<Arc<AppState>>,
+    Json(bmv): Json<Go>,
+) -> Result<Json<Bestmove>, (StatusCode, String)> {
     println!("POST: /api/bestmove; {}, {}s", bmv.mfen, bmv.time);
-    let mut position = Position::from_str(&bmv.mfen).unwrap();
-    if let Some(info) = search(&mut position, bmv.time) {
+    // Search the current board if it matches so that the history of moves is taken into account.
+    let mut position = {
+        let current = state.position.lock().unwrap();
+        if current.to_string() == bmv.mfen {
+            current.clone()
+        } else {
+            Position::from_str(&bmv.mfen).map_err(bad_request)?
+        }
+    };
+    let limits = SearchLimits::movetime((bmv.time * 1000.0) as u64);
+    let multi_pv = bmv.multipv.unwrap_or(1).max(1);
+    let options = SearchOptions {
+        threads: state.threads,
+        multi_pv,
+        rule: state.rule,
+        ..Default::default()
+    };
+    // The search blocks the thread, so it must not run on the async runtime.
+    let (position, info) = tokio::task::spawn_blocking(move || {
+        let info = search(
+            &mut position,
+            &limits,
+            &state.tt,
+            &options,
+            &Signals::default(),
+            &mut |_| {},
+        );
+        (position, info)
+    })
+    .await
+    .unwrap();
+    if let Some(info) = info {
+        let side = position.side;
         let mut root_moves = Vec::new();
         let mut pv = Vec::new();
-        for (mv, value, line) in info.root_moves {
-            root_moves.push((move_to_mfen(mv, position.side), value));
+        let mut lines = Vec::new();
+        for (i, (mv, value, line)) in info.root_moves.into_iter().enumerate() {
+            root_moves.push((move_to_mfen(mv, side), value));
             if mv == info.mv {
-                for mv in line {
-                    pv.push(move_to_mfen(mv, position.side));
-                }
+                pv = moves_to_mfen(&line, !side);
+            }
+            if i < multi_pv {
+                lines.push(PvLine {
+                    mfen: move_to_mfen(mv, side),
+                    value,
+                    pv: moves_to_mfen(&line, !side),
+                });
             }
         }
-        Json(Bestmove {
-            mfen: move_to_mfen(info.mv, position.side),
+        Ok(Json(Bestmove {
+            mfen: move_to_mfen(info.mv, side),
             depth: info.depth,
             value: info.value,
             root_moves,
             pv,
-        })
+            lines,
+        }))
     } else {
-        Json(Bestmove {
+        Ok(Json(Bestmove {
             mfen: "resign".to_string(),
             depth: 0,
             value: 0,
             root_moves: Vec::new(),
             pv: Vec::new(),
-        })
+            lines: Vec::new(),
+        }))
     }
 }
+
+#[derive(Deserialize)]
+pub struct Mate {
+    mfen: String,
+    /// Maximum count of nodes. `DEFAULT_MATE_NODES` if omitted.
+    nodes: Option<u64>,
+}
+
+/// Default node budget of a mate search.
+const DEFAULT_MATE_NODES: u64 = 1_000_000;
+
+#[derive(Serialize)]
+pub struct MateJson {
+    /// "mate", "nomate" or "unknown".
+    result: String,
+    /// Moves of the mate.
+    moves: Vec<String>,
+}
+
+pub async fn post_mate(
+    State(state): State<Arc<AppState>>,
+    Json(mate): Json<Mate>,
+) -> Result<Json<MateJson>, (StatusCode, String)> {
+    println!("POST: /api/mate; {}", mate.mfen);
+    // Search the current board if it matches so that the history of moves is taken into account.
+    let mut position = {
+        let current = state.position.lock().unwrap();
+        if current.to_string() == mate.mfen {
+            current.clone()
+        } else {
+            Position::from_str(&mate.mfen).map_err(bad_request)?
+        }
+    };
+    let limits = MateLimits {
+        nodes: Some(mate.nodes.unwrap_or(DEFAULT_MATE_NODES)),
+        ..Default::default()
+    };
+    // The search blocks the thread, so it must not run on the async runtime.
+    let (position, result) = tokio::task::spawn_blocking(move || {
+        let result = mate_search(&mut position, &limits, &Signals::default());
+        (position, result)
+    })
+    .await
+    .unwrap();
+    Ok(Json(match result {
+        MateResult::Mate(moves) => MateJson {
+            result: "mate".to_string(),
+            moves: moves_to_mfen(&moves, position.side),
+        },
+        MateResult::NoMate => MateJson {
+            result: "nomate".to_string(),
+            moves: Vec::new(),
+        },
+        MateResult::Unknown => MateJson {
+            result: "unknown".to_string(),
+            moves: Vec::new(),
+        },
+    }))
+}
@@ -4,8 +4,12 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use alex::position::Position;
-use api::{get_board, post_bestmove, post_board, post_move};
+use alex::{
+    position::Position,
+    search::RepetitionRule,
+    tt::{TranspositionTable, DEFAULT_HASH_SIZE},
+};
+use api::{get_board, get_result, post_bestmove, post_board, post_mate, post_move, AppState};
 use axum::{
     http::{header::CONTENT_TYPE, HeaderValue},
     routing::{get, post},
@@ -23,14 +27,35 @@ async fn main() {
         .route("/api/board", get(get_board))
         .route("/api/board", post(post_board))
         .route("/api/move", post(post_move))
-        .route("/api/bestmove", post(post_bestmove));
+        .route("/api/result", get(get_result))
+        .route("/api/bestmove", post(post_bestmove))
+        .route("/api/mate", post(post_mate));
     if !args.contains(&"--server-only".to_string()) {
         let static_dir = ServeDir::new("static");
         app = app.nest_service("/", static_dir);
     }
+    // `--threads N` sets the count of search threads.
+    let threads = args
+        .iter()
+        .position(|arg| arg == "--threads")
+        .and_then(|i| args.get(i + 1))
+        .map_or(1, |n| n.parse().expect("invalid count of threads"));
+    // `--rule NAME` sets the rule of repetition, such as `Draw`.
+    let rule = args
+        .iter()
+        .position(|arg| arg == "--rule")
+        .and_then(|i| args.get(i + 1))
+        .map_or(RepetitionRule::default(), |name| {
+            RepetitionRule::from_name(name).expect("invalid rule of repetition")
+        });
     let position =
         Position::from_str("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0").unwrap();
-    let state = Arc::new(Mutex::new(position));
+    let state = Arc::new(AppState {
+        position: Mutex::new(position),
+        tt: TranspositionTable::new(DEFAULT_HASH_SIZE),
+        threads,
+        rule,
+    });
     let origins = ["http://127.0.0.1:5173".parse::<HeaderValue>().unwrap()];
     let app = app.with_state(state).layer(
         CorsLayer::new()
@@ -9,7 +9,7 @@ use crate::{
     types::{Bitboard, PieceType, Side, Square, PIECE_NB, PIECE_TYPE_NB, RANK_NB, SQUARE_NB},
 };
 
-pub static KG_BITBOARD: LazyLock<Bitboards> = LazyLock::new(|| Bitboards::new());
+pub static KG_BITBOARD: LazyLock<Bitboards> = LazyLock::new(Bitboards::new);
 
 macro_rules! for_pos {
     ($ix:ident, $iy:ident, $i:ident, $e:expr) => {
@@ -51,6 +51,7 @@ pub struct Bitboards {
 }
 
 impl Bitboards {
+    #[allow(clippy::needless_range_loop)]
     fn new() -> Self {
         let mut movable_sq = [[0; SQUARE_NB]; PIECE_NB];
         for pt in PieceType::iter() {
@@ -206,7 +207,7 @@ impl Bitboards {
                     for k in -8..9 {
                         let x = ix as isize + (jx as isize - ix as isize) * k / d as isize;
                         let y = iy as isize + (jy as isize - iy as isize) * k / d as isize;
-                        if 0 <= x && x < 8 && 0 <= y && y < 8 {
+                        if (0..8).contains(&x) && (0..8).contains(&y) {
                             change_bit!(line_bb[i][j], y as usize * RANK_NB + x as usize);
                         }
                     }
@@ -0,0 +1,70 @@
+use std::{collections::HashMap, fmt, fs, io, path::Path, str::FromStr};
+
+use crate::{
+    position::{MfenError, Position},
+    types::{Key, Move, MoveError},
+};
+
+/// Error of loading a book. Lines are counted from 1.
+#[derive(Debug)]
+pub enum BookError {
+    Io(io::Error),
+    /// The line has less than the five fields of MFEN.
+    Syntax(usize, String),
+    InvalidMfen(usize, MfenError),
+    InvalidMove(usize, MoveError),
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::Io(e) => write!(f, "{}", e),
+            BookError::Syntax(line, s) => write!(f, "invalid line {}: {}", line, s),
+            BookError::InvalidMfen(line, e) => write!(f, "line {}: {}", line, e),
+            BookError::InvalidMove(line, e) => write!(f, "line {}: {}", line, e),
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+/// Opening book.
+#[derive(Default)]
+pub struct Book {
+    moves: HashMap<Key, Vec<Move>>,
+}
+
+impl Book {
+    /// Reads a book from a file.
+    /// Each line is a position in MFEN followed by its book moves, the preferred one first.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BookError> {
+        let text = fs::read_to_string(path).map_err(BookError::Io)?;
+        let mut book = Book::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.len() < 5 {
+                return Err(BookError::Syntax(i + 1, line.to_string()));
+            }
+            let position = Position::from_str(&tokens[..5].join(" "))
+                .map_err(|e| BookError::InvalidMfen(i + 1, e))?;
+            let moves = book.moves.entry(position.key()).or_default();
+            for token in &tokens[5..] {
+                let mv = position
+                    .read_move(token.to_string())
+                    .map_err(|e| BookError::InvalidMove(i + 1, e))?;
+                moves.push(mv);
+            }
+        }
+        Ok(book)
+    }
+
+    /// Returns the preferred book move of the position.
+    pub fn probe(&self, position: &Position) -> Option<Move> {
+        self.moves.get(&position.key())?.first().copied()
+    }
+}
@@ -1,4 +1,8 @@
-use std::sync::LazyLock;
+use std::{
+    fmt, fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
 use num_traits::FromPrimitive;
 
@@ -12,10 +16,10 @@ use super::{
     types::{PieceType, Value, PIECE_TYPE_NB, SQUARE_NB},
 };
 
-//                                                 NONE  L    H    K    P    G    N    R    A0   A1   A2
+//                                                       NONE  L    H    K    P    G    N    R    A0   A1   A2
 // Value of a piece.
 #[rustfmt::skip]
-const PIECE_VALUES: [Value; PIECE_TYPE_NB]      = [0,    100, 200, 800, 600, 400, 400, 400, 400, 800, 1200];
+pub(crate) const PIECE_VALUES: [Value; PIECE_TYPE_NB] = [0,    100, 200, 800, 600, 400, 400, 400, 400, 800, 1200];
 
 const PARAM_OUR_EFFECT_VALUE: i32 = 70;
 const PARAM_OPP_EFFECT_VALUE: i32 = 100;
@@ -27,7 +31,120 @@ const PARAM_OPP_EFFECT_PIECE_VALUE_2: i32 = 30;
 const PARAM_HAND_PIECE_VALUE: i32 = 200;
 const PARAM_DEMISE_VALUE: i16 = 200;
 
-static KKPEE: LazyLock<Vec<Value>> = LazyLock::new(|| init_kkpee());
+/// Parameters of the evaluation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EvalParams {
+    pub our_effect: i32,
+    pub opp_effect: i32,
+    pub multi_effect: i32,
+    pub our_effect_piece_1: i32,
+    pub our_effect_piece_2: i32,
+    pub opp_effect_piece_1: i32,
+    pub opp_effect_piece_2: i32,
+    pub hand_piece: i32,
+    pub demise: i16,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        EvalParams {
+            our_effect: PARAM_OUR_EFFECT_VALUE,
+            opp_effect: PARAM_OPP_EFFECT_VALUE,
+            multi_effect: PARAM_MULTI_EFFECT_VALUE,
+            our_effect_piece_1: PARAM_OUR_EFFECT_PIECE_VALUE_1,
+            our_effect_piece_2: PARAM_OUR_EFFECT_PIECE_VALUE_2,
+            opp_effect_piece_1: PARAM_OPP_EFFECT_PIECE_VALUE_1,
+            opp_effect_piece_2: PARAM_OPP_EFFECT_PIECE_VALUE_2,
+            hand_piece: PARAM_HAND_PIECE_VALUE,
+            demise: PARAM_DEMISE_VALUE,
+        }
+    }
+}
+
+/// Error of loading the parameters of the evaluation.
+#[derive(Debug)]
+pub enum EvalParamsError {
+    Io(io::Error),
+    /// The line at the number, counted from 1, is not a known name and a valid value.
+    Syntax(usize, String),
+}
+
+impl fmt::Display for EvalParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalParamsError::Io(e) => write!(f, "{}", e),
+            EvalParamsError::Syntax(line, s) => write!(f, "invalid line {}: {}", line, s),
+        }
+    }
+}
+
+impl std::error::Error for EvalParamsError {}
+
+impl EvalParams {
+    /// Reads parameters from a file of `name value` lines.
+    /// Missing parameters keep the default, and lines starting with `#` are ignored.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EvalParamsError> {
+        let text = fs::read_to_string(path).map_err(EvalParamsError::Io)?;
+        let mut params = EvalParams::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || EvalParamsError::Syntax(i + 1, line.to_string());
+            let mut tokens = line.split_whitespace();
+            let (Some(name), Some(value), None) = (tokens.next(), tokens.next(), tokens.next())
+            else {
+                return Err(error());
+            };
+            let value: i32 = value.parse().map_err(|_| error())?;
+            match name {
+                "our_effect" => params.our_effect = value,
+                "opp_effect" => params.opp_effect = value,
+                "multi_effect" => params.multi_effect = value,
+                "our_effect_piece_1" => params.our_effect_piece_1 = value,
+                "our_effect_piece_2" => params.our_effect_piece_2 = value,
+                "opp_effect_piece_1" => params.opp_effect_piece_1 = value,
+                "opp_effect_piece_2" => params.opp_effect_piece_2 = value,
+                "hand_piece" => params.hand_piece = value,
+                "demise" => params.demise = Value::try_from(value).map_err(|_| error())?,
+                _ => return Err(error()),
+            }
+        }
+        Ok(params)
+    }
+}
+
+/// Tables of the evaluation built with the parameters.
+pub struct Evaluator {
+    params: EvalParams,
+    kkpee: Vec<Value>,
+}
+
+/// Evaluator built with the last parameters.
+static EVALUATOR: Mutex<Option<Arc<Evaluator>>> = Mutex::new(None);
+
+/// Returns the evaluator with the parameters, rebuilding the tables if they changed.
+/// Searches already running keep the evaluator they started with.
+pub fn evaluator(params: &EvalParams) -> Arc<Evaluator> {
+    let mut evaluator = EVALUATOR.lock().unwrap();
+    match evaluator.as_ref() {
+        Some(e) if e.params == *params => e.clone(),
+        _ => {
+            let e = Arc::new(Evaluator {
+                params: *params,
+                kkpee: init_kkpee(params),
+            });
+            *evaluator = Some(e.clone());
+            e
+        }
+    }
+}
+
+/// Builds the tables of the evaluation in advance.
+pub fn init(params: &EvalParams) {
+    evaluator(params);
+}
 
 fn index_kkpee(bking: usize, wking: usize, sq: usize, pc: Piece, m1: usize, m2: usize) -> usize {
     bking * SQUARE_NB * SQUARE_NB * PIECE_NB * 3 * 3
@@ -42,14 +159,14 @@ fn dist(sq1: usize, sq2: usize) -> usize {
     (sq1 / 8).abs_diff(sq2 / 8).max((sq1 % 8).abs_diff(sq2 % 8))
 }
 
-fn init_kkpee() -> Vec<Value> {
+fn init_kkpee(params: &EvalParams) -> Vec<Value> {
     let mut our_eff = [0; 9];
     let mut opp_eff = [0; 9];
     for d in 0..9 {
-        our_eff[d] = PARAM_OUR_EFFECT_VALUE * 1024 / (d as i32 + 1);
-        opp_eff[d] = PARAM_OPP_EFFECT_VALUE * 1024 / (d as i32 + 1);
+        our_eff[d] = params.our_effect * 1024 / (d as i32 + 1);
+        opp_eff[d] = params.opp_effect * 1024 / (d as i32 + 1);
     }
-    let multi_eff = [0, 1024, PARAM_MULTI_EFFECT_VALUE];
+    let multi_eff = [0, 1024, params.multi_effect];
     let mut our_eff_table = [[[0.0; 3]; SQUARE_NB]; SQUARE_NB];
     let mut opp_eff_table = [[[0.0; 3]; SQUARE_NB]; SQUARE_NB];
     for king in 0..SQUARE_NB {
@@ -63,16 +180,8 @@ fn init_kkpee() -> Vec<Value> {
             }
         }
     }
-    let our_eff_to_piece = [
-        0,
-        PARAM_OUR_EFFECT_PIECE_VALUE_1,
-        PARAM_OUR_EFFECT_PIECE_VALUE_2,
-    ];
-    let opp_eff_to_piece = [
-        0,
-        PARAM_OPP_EFFECT_PIECE_VALUE_1,
-        PARAM_OPP_EFFECT_PIECE_VALUE_2,
-    ];
+    let our_eff_to_piece = [0, params.our_effect_piece_1, params.our_effect_piece_2];
+    let opp_eff_to_piece = [0, params.opp_effect_piece_1, params.opp_effect_piece_2];
 
     let mut kkpee = vec![0; SQUARE_NB * SQUARE_NB * SQUARE_NB * PIECE_NB * 3 * 3];
 
@@ -92,8 +201,7 @@ fn init_kkpee() -> Vec<Value> {
                             score -= our_eff_table[wking][sq][m2];
                             score -= opp_eff_table[bking][sq][m2];
                             if pc != Piece::None {
-                                let s = PIECE_VALUES[pc.pt() as usize] as i32
-                                    * PARAM_HAND_PIECE_VALUE
+                                let s = PIECE_VALUES[pc.pt() as usize] as i32 * params.hand_piece
                                     / 1024;
                                 if pc.side() == Side::Black {
                                     score += our_eff_to_piece[m1] as f64;
@@ -116,66 +224,68 @@ fn init_kkpee() -> Vec<Value> {
     kkpee
 }
 
-/// Returns a static evaluation of the position from the point of view of the side to move.
-pub fn eval(position: &Position) -> Value {
-    let mut value = 0;
-    let black_pieces = position.piece_count[Side::Black as usize];
-    let white_pieces = position.piece_count[Side::White as usize];
-    for i in 1..PIECE_TYPE_NB {
-        let pt = PieceType::from_usize(i).unwrap();
-        value += PIECE_VALUES[i] * (black_pieces[i] as Value - white_pieces[i] as Value);
-        if pt != PieceType::King
-            && pt != PieceType::Prince
-            && pt != PieceType::Archer1
-            && pt != PieceType::Archer2
-        {
-            value += PIECE_VALUES[i] * position.count_hand(Side::Black, pt) as Value;
-            value -= PIECE_VALUES[i] * position.count_hand(Side::White, pt) as Value;
+impl Evaluator {
+    /// Returns a static evaluation of the position from the point of view of the side to move.
+    pub fn eval(&self, position: &Position) -> Value {
+        let mut value = 0;
+        let black_pieces = position.piece_count[Side::Black as usize];
+        let white_pieces = position.piece_count[Side::White as usize];
+        for i in 1..PIECE_TYPE_NB {
+            let pt = PieceType::from_usize(i).unwrap();
+            value += PIECE_VALUES[i] * (black_pieces[i] as Value - white_pieces[i] as Value);
+            if pt != PieceType::King
+                && pt != PieceType::Prince
+                && pt != PieceType::Archer1
+                && pt != PieceType::Archer2
+            {
+                value += PIECE_VALUES[i] * position.count_hand(Side::Black, pt) as Value;
+                value -= PIECE_VALUES[i] * position.count_hand(Side::White, pt) as Value;
+            }
         }
-    }
 
-    let mut black_effects = position.effects[Side::Black as usize];
-    let mut white_effects = position.effects[Side::White as usize];
-    let black_archer: u64 = position.pieces_pt_side(PieceType::Archer1, Side::Black)
-        | position.pieces_pt_side(PieceType::Archer2, Side::Black);
-    let white_archer: u64 = position.pieces_pt_side(PieceType::Archer1, Side::White)
-        | position.pieces_pt_side(PieceType::Archer2, Side::White);
-    foreach_bb!(black_archer, sq, {
-        foreach_bb!(position.arrow_attacks(sq), sq2, {
-            black_effects[sq2 as usize] += 1;
+        let mut black_effects = position.effects[Side::Black as usize];
+        let mut white_effects = position.effects[Side::White as usize];
+        let black_archer: u64 = position.pieces_pt_side(PieceType::Archer1, Side::Black)
+            | position.pieces_pt_side(PieceType::Archer2, Side::Black);
+        let white_archer: u64 = position.pieces_pt_side(PieceType::Archer1, Side::White)
+            | position.pieces_pt_side(PieceType::Archer2, Side::White);
+        foreach_bb!(black_archer, sq, {
+            foreach_bb!(position.arrow_attacks(sq), sq2, {
+                black_effects[sq2 as usize] += 1;
+            });
         });
-    });
-    foreach_bb!(white_archer, sq, {
-        foreach_bb!(position.arrow_attacks(sq), sq2, {
-            white_effects[sq2 as usize] += 1;
+        foreach_bb!(white_archer, sq, {
+            foreach_bb!(position.arrow_attacks(sq), sq2, {
+                white_effects[sq2 as usize] += 1;
+            });
+        });
+        foreach_bb!(position.heavy_attacks(Side::Black), sq, {
+            black_effects[sq as usize] += 1;
+        });
+        foreach_bb!(position.heavy_attacks(Side::White), sq, {
+            white_effects[sq as usize] += 1;
         });
-    });
-    foreach_bb!(position.heavy_attacks(Side::Black), sq, {
-        black_effects[sq as usize] += 1;
-    });
-    foreach_bb!(position.heavy_attacks(Side::White), sq, {
-        white_effects[sq as usize] += 1;
-    });
-
-    let bking = position.crown_sq(Side::Black) as usize;
-    let wking = position.crown_sq(Side::White) as usize;
-    for sq in 0..SQUARE_NB {
-        value += KKPEE[index_kkpee(
-            bking,
-            wking,
-            sq,
-            position.grid[sq],
-            black_effects[sq].min(2),
-            white_effects[sq].min(2),
-        )];
-    }
 
-    value -= PARAM_DEMISE_VALUE * position.demise[Side::Black as usize] as Value;
-    value += PARAM_DEMISE_VALUE * position.demise[Side::White as usize] as Value;
+        let bking = position.crown_sq(Side::Black) as usize;
+        let wking = position.crown_sq(Side::White) as usize;
+        for sq in 0..SQUARE_NB {
+            value += self.kkpee[index_kkpee(
+                bking,
+                wking,
+                sq,
+                position.grid[sq],
+                black_effects[sq].min(2),
+                white_effects[sq].min(2),
+            )];
+        }
 
-    if position.side == Side::Black {
-        value
-    } else {
-        -value
+        value -= self.params.demise * position.demise[Side::Black as usize] as Value;
+        value += self.params.demise * position.demise[Side::White as usize] as Value;
+
+        if position.side == Side::Black {
+            value
+        } else {
+            -value
+        }
     }
 }
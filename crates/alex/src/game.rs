@@ -0,0 +1,162 @@
+use core::fmt;
+use std::str::FromStr;
+
+use crate::{
+    movegen::{GenType, MoveList},
+    position::{Position, RepetitionState},
+    types::Side,
+};
+
+/// Count of appearances of the same position to end the game (sennichite).
+pub const REPETITION_COUNT: usize = 4;
+
+/// Reason why a side won.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WinReason {
+    /// The crown of the loser is in check and cannot escape.
+    Checkmate,
+    /// The loser is not in check but has no legal moves.
+    NoLegalMoves,
+    /// The loser repeated the position by checking perpetually.
+    PerpetualCheck,
+}
+
+/// Reason of a draw.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DrawReason {
+    /// The same position appeared `REPETITION_COUNT` times.
+    Repetition,
+    /// The game reached the limit of moves.
+    MoveLimit,
+}
+
+/// Result of a game.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    /// The game is not over.
+    Ongoing,
+    Win(Side, WinReason),
+    Draw(DrawReason),
+}
+
+impl fmt::Display for WinReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WinReason::Checkmate => write!(f, "checkmate"),
+            WinReason::NoLegalMoves => write!(f, "nolegalmoves"),
+            WinReason::PerpetualCheck => write!(f, "perpetualcheck"),
+        }
+    }
+}
+
+impl fmt::Display for DrawReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DrawReason::Repetition => write!(f, "repetition"),
+            DrawReason::MoveLimit => write!(f, "movelimit"),
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameResult::Ongoing => write!(f, "ongoing"),
+            GameResult::Win(Side::Black, reason) => write!(f, "black {}", reason),
+            GameResult::Win(Side::White, reason) => write!(f, "white {}", reason),
+            GameResult::Draw(reason) => write!(f, "draw {}", reason),
+        }
+    }
+}
+
+/// Error of reading a game result.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GameResultError {
+    InvalidReason(String),
+    InvalidResult(String),
+}
+
+impl fmt::Display for GameResultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameResultError::InvalidReason(s) => write!(f, "invalid reason: {}", s),
+            GameResultError::InvalidResult(s) => write!(f, "invalid result: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for GameResultError {}
+
+impl FromStr for WinReason {
+    type Err = GameResultError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checkmate" => Ok(WinReason::Checkmate),
+            "nolegalmoves" => Ok(WinReason::NoLegalMoves),
+            "perpetualcheck" => Ok(WinReason::PerpetualCheck),
+            _ => Err(GameResultError::InvalidReason(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for DrawReason {
+    type Err = GameResultError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repetition" => Ok(DrawReason::Repetition),
+            "movelimit" => Ok(DrawReason::MoveLimit),
+            _ => Err(GameResultError::InvalidReason(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = GameResultError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(' ') {
+            None if s == "ongoing" => Ok(GameResult::Ongoing),
+            Some(("black", reason)) => Ok(GameResult::Win(Side::Black, reason.parse()?)),
+            Some(("white", reason)) => Ok(GameResult::Win(Side::White, reason.parse()?)),
+            Some(("draw", reason)) => Ok(GameResult::Draw(reason.parse()?)),
+            _ => Err(GameResultError::InvalidResult(s.to_string())),
+        }
+    }
+}
+
+impl Position {
+    /// Returns the result of the game.
+    /// The game is drawn after `move_limit` plies if it is given.
+    pub fn game_result(&self, move_limit: Option<usize>) -> GameResult {
+        let mut moves = MoveList::new();
+        moves.generate(self, GenType::Legal);
+        if moves.size == 0 {
+            // Demise moves are included in the evasions, so the crown after demise is checked.
+            let reason = if self.checkers() != 0 {
+                WinReason::Checkmate
+            } else {
+                WinReason::NoLegalMoves
+            };
+            return GameResult::Win(!self.side, reason);
+        }
+
+        let (count, state) = self.repetition_count();
+        if count >= REPETITION_COUNT {
+            return match state {
+                RepetitionState::Win => GameResult::Win(self.side, WinReason::PerpetualCheck),
+                RepetitionState::Lose => GameResult::Win(!self.side, WinReason::PerpetualCheck),
+                _ => GameResult::Draw(DrawReason::Repetition),
+            };
+        }
+
+        if let Some(limit) = move_limit {
+            if self.game_ply() >= limit {
+                return GameResult::Draw(DrawReason::MoveLimit);
+            }
+        }
+
+        GameResult::Ongoing
+    }
+}
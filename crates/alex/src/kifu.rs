@@ -0,0 +1,218 @@
+use core::fmt;
+use std::str::FromStr;
+
+use crate::{
+    game::GameResult,
+    position::{MfenError, Position, STARTPOS},
+    types::{move_to_mfen, Move, MoveError, Side, Value, MOVE_DEMISE},
+};
+
+/// A move of a game record with its annotations.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KifuMove {
+    pub mv: Move,
+    /// Time spent on the move in milliseconds.
+    pub time: Option<u64>,
+    /// Evaluation from the point of view of the mover.
+    pub eval: Option<Value>,
+    pub comment: Option<String>,
+}
+
+impl KifuMove {
+    pub fn new(mv: Move) -> Self {
+        KifuMove {
+            mv,
+            time: None,
+            eval: None,
+            comment: None,
+        }
+    }
+}
+
+/// Game record.
+///
+/// The text format is a header of `Key: value` lines, an empty line and a line per move:
+///
+/// ```text
+/// Black: alice
+/// White: alex
+/// Date: 2024-01-31
+/// MFEN: bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0
+/// Result: ongoing
+///
+/// 1 C2C3 time 1200 eval 35 ; Opening the file of the archer.
+/// 2 F7F6
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Kifu {
+    pub black: Option<String>,
+    pub white: Option<String>,
+    /// Date of the game such as `2024-01-31`.
+    pub date: Option<String>,
+    /// MFEN of the initial position.
+    pub mfen: String,
+    pub moves: Vec<KifuMove>,
+    pub result: GameResult,
+}
+
+/// Error of reading a game record.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum KifuError {
+    /// The line at the number, counted from 1, is not in the format.
+    Syntax(usize, String),
+    InvalidMfen(String, MfenError),
+    /// The move at the ply, counted from 1, is not legal.
+    IllegalMove(usize, MoveError),
+}
+
+impl fmt::Display for KifuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KifuError::Syntax(line, s) => write!(f, "invalid line {}: {}", line, s),
+            KifuError::InvalidMfen(mfen, e) => write!(f, "invalid mfen {}: {}", mfen, e),
+            KifuError::IllegalMove(ply, e) => write!(f, "ply {}: {}", ply, e),
+        }
+    }
+}
+
+impl std::error::Error for KifuError {}
+
+impl Default for Kifu {
+    fn default() -> Self {
+        Kifu::new(STARTPOS.to_string())
+    }
+}
+
+impl Kifu {
+    pub fn new(mfen: String) -> Self {
+        Kifu {
+            black: None,
+            white: None,
+            date: None,
+            mfen,
+            moves: Vec::new(),
+            result: GameResult::Ongoing,
+        }
+    }
+
+    /// Returns the position after all the moves, checking that they are legal.
+    pub fn position(&self) -> Result<Position, KifuError> {
+        let mut position = Position::from_str(&self.mfen)
+            .map_err(|e| KifuError::InvalidMfen(self.mfen.clone(), e))?;
+        for (i, m) in self.moves.iter().enumerate() {
+            if !position.is_pseudo_legal(m.mv) || !position.is_legal(m.mv) {
+                let mv = move_to_mfen(m.mv, position.side);
+                return Err(KifuError::IllegalMove(i + 1, MoveError::Illegal(mv)));
+            }
+            position.do_move(m.mv, None);
+        }
+        Ok(position)
+    }
+}
+
+/// Removes line breaks, which end a field.
+pub(crate) fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+impl fmt::Display for Kifu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let headers = [
+            ("Black", &self.black),
+            ("White", &self.white),
+            ("Date", &self.date),
+        ];
+        for (key, value) in headers {
+            if let Some(value) = value {
+                writeln!(f, "{}: {}", key, single_line(value))?;
+            }
+        }
+        writeln!(f, "MFEN: {}", self.mfen)?;
+        writeln!(f, "Result: {}", self.result)?;
+        writeln!(f)?;
+
+        let mut side = Position::from_str(&self.mfen).map_or(Side::Black, |p| p.side);
+        for (i, m) in self.moves.iter().enumerate() {
+            if m.mv == MOVE_DEMISE {
+                write!(f, "{} D", i + 1)?;
+            } else {
+                write!(f, "{} {}", i + 1, move_to_mfen(m.mv, side))?;
+                // Demise alone does not pass the turn.
+                side = !side;
+            }
+            if let Some(time) = m.time {
+                write!(f, " time {}", time)?;
+            }
+            if let Some(eval) = m.eval {
+                write!(f, " eval {}", eval)?;
+            }
+            if let Some(comment) = &m.comment {
+                write!(f, " ; {}", single_line(comment))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Kifu {
+    type Err = KifuError;
+
+    /// Reads a game record, replaying the moves to check them.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut kifu = Kifu::default();
+        let mut lines = s.lines().enumerate();
+        for (i, line) in lines.by_ref() {
+            if line.trim().is_empty() {
+                break;
+            }
+            let syntax = || KifuError::Syntax(i + 1, line.to_string());
+            let (key, value) = line.split_once(':').ok_or_else(syntax)?;
+            let value = value.trim().to_string();
+            match key.trim() {
+                "Black" => kifu.black = Some(value),
+                "White" => kifu.white = Some(value),
+                "Date" => kifu.date = Some(value),
+                "MFEN" => kifu.mfen = value,
+                "Result" => kifu.result = value.parse().map_err(|_| syntax())?,
+                _ => return Err(syntax()),
+            }
+        }
+
+        let mut position = Position::from_str(&kifu.mfen)
+            .map_err(|e| KifuError::InvalidMfen(kifu.mfen.clone(), e))?;
+        for (i, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let syntax = || KifuError::Syntax(i + 1, line.to_string());
+            let (body, comment) = match line.split_once(';') {
+                Some((body, comment)) => (body, Some(comment.trim().to_string())),
+                None => (line, None),
+            };
+            let mut tokens = body.split_whitespace();
+            let ply = kifu.moves.len() + 1;
+            if tokens.next() != Some(&ply.to_string()) {
+                return Err(syntax());
+            }
+            let mv_str = tokens.next().ok_or_else(syntax)?;
+            let mv = position
+                .read_move(mv_str.to_string())
+                .map_err(|e| KifuError::IllegalMove(ply, e))?;
+            position.do_move(mv, None);
+
+            let mut m = KifuMove::new(mv);
+            m.comment = comment;
+            while let Some(key) = tokens.next() {
+                let value = tokens.next().ok_or_else(syntax)?;
+                match key {
+                    "time" => m.time = Some(value.parse().map_err(|_| syntax())?),
+                    "eval" => m.eval = Some(value.parse().map_err(|_| syntax())?),
+                    _ => return Err(syntax()),
+                }
+            }
+            kifu.moves.push(m);
+        }
+        Ok(kifu)
+    }
+}
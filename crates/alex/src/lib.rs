@@ -1,10 +1,21 @@
 pub mod bitboard;
+pub mod book;
 pub mod eval;
+pub mod game;
+pub mod kifu;
+pub mod mate;
 pub mod movegen;
 pub mod movepick;
+pub mod notation;
+pub mod options;
 pub mod perft;
+pub mod pgn;
 pub mod position;
 pub mod search;
+pub mod timeman;
+pub mod tt;
 pub mod types;
+pub mod zobrist;
 
+mod see;
 mod test;
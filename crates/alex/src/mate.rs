@@ -0,0 +1,256 @@
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+use crate::{
+    movegen::{GenType, MoveList},
+    position::{Position, RepetitionState},
+    search::Signals,
+    timeman::{Clock, SystemClock},
+    types::{Key, Move, MAX_PLY},
+};
+
+/// Proof or disproof number which means proven or disproven.
+const INF: u32 = u32::MAX / 2;
+
+/// Limits of a mate search.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MateLimits {
+    /// Maximum count of nodes.
+    pub nodes: Option<u64>,
+    /// Maximum time in milliseconds.
+    pub time: Option<u64>,
+}
+
+/// Result of a mate search.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MateResult {
+    /// Moves of the attacker and the defender alternately, ending with the mate.
+    Mate(Vec<Move>),
+    /// The attacker cannot mate by checking continuously.
+    NoMate,
+    /// The limits were reached before the search finished.
+    Unknown,
+}
+
+/// Proof and disproof numbers of a node from the point of view of the attacker.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Entry {
+    pn: u32,
+    dn: u32,
+    /// Plies to the mate if proven.
+    len: u32,
+}
+
+impl Entry {
+    const UNKNOWN: Entry = Entry {
+        pn: 1,
+        dn: 1,
+        len: 0,
+    };
+    const PROVEN: Entry = Entry {
+        pn: 0,
+        dn: INF,
+        len: 0,
+    };
+    const DISPROVEN: Entry = Entry {
+        pn: INF,
+        dn: 0,
+        len: 0,
+    };
+
+    /// Returns the number to minimize and the number to sum up at the node.
+    fn phi_delta(&self, or_node: bool) -> (u32, u32) {
+        if or_node {
+            (self.pn, self.dn)
+        } else {
+            (self.dn, self.pn)
+        }
+    }
+
+    fn from_phi_delta(phi: u32, delta: u32, or_node: bool) -> Self {
+        let (pn, dn) = if or_node { (phi, delta) } else { (delta, phi) };
+        Entry { pn, dn, len: 0 }
+    }
+}
+
+/// Returns the moves of the side to move at the node.
+/// The attacker plays only checking moves, and the defender plays all evasions.
+fn generate(position: &Position, or_node: bool) -> Vec<Move> {
+    let mut list = MoveList::new();
+    if !or_node {
+        list.generate(position, GenType::Legal);
+        return (0..list.size).map(|i| list.at(i).mv).collect();
+    }
+    if position.checkers() == 0 {
+        list.generate(position, GenType::Checks);
+        return (0..list.size)
+            .map(|i| list.at(i).mv)
+            .filter(|&mv| position.is_legal(mv))
+            .collect();
+    }
+    // The attacker in check plays evasions giving check.
+    list.generate(position, GenType::Legal);
+    (0..list.size)
+        .map(|i| list.at(i).mv)
+        .filter(|&mv| position.gives_check(mv))
+        .collect()
+}
+
+/// Depth-first proof-number search.
+struct Solver<'a> {
+    table: HashMap<Key, Entry>,
+    limits: &'a MateLimits,
+    signals: &'a Signals,
+    clock: SystemClock,
+    start: u64,
+    nodes: u64,
+    aborted: bool,
+}
+
+impl Solver<'_> {
+    fn limit_reached(&mut self) -> bool {
+        if !self.aborted {
+            // The clock is read only every 1024 nodes.
+            self.aborted = self.limits.nodes.is_some_and(|n| self.nodes >= n)
+                || self.signals.stop.load(Ordering::Relaxed)
+                || (self.nodes.is_multiple_of(1024)
+                    && self
+                        .limits
+                        .time
+                        .is_some_and(|t| self.clock.now().saturating_sub(self.start) >= t));
+        }
+        self.aborted
+    }
+
+    fn entry(&self, key: Key) -> Entry {
+        self.table.get(&key).copied().unwrap_or(Entry::UNKNOWN)
+    }
+
+    /// Searches the node until its phi or delta reaches the threshold, and returns its entry.
+    /// The attacker is to move at an OR node.
+    fn mid(
+        &mut self,
+        position: &mut Position,
+        or_node: bool,
+        th_phi: u32,
+        th_delta: u32,
+        ply: usize,
+    ) -> Entry {
+        self.nodes += 1;
+        // Repetition is not a win for the attacker.
+        // The result depends on the path, so it is not stored.
+        if position.repetition_state() != RepetitionState::None || ply >= MAX_PLY {
+            return Entry::DISPROVEN;
+        }
+        let key = position.key();
+        let moves = generate(position, or_node);
+        if moves.is_empty() {
+            let entry = if or_node {
+                Entry::DISPROVEN
+            } else {
+                Entry::PROVEN
+            };
+            self.table.insert(key, entry);
+            return entry;
+        }
+        let mut children: Vec<(Move, Key, Entry)> = moves
+            .into_iter()
+            .map(|mv| {
+                position.do_move(mv, None);
+                let key = position.key();
+                position.undo_move(mv);
+                (mv, key, self.entry(key))
+            })
+            .collect();
+
+        loop {
+            // A child is an OR node if this is an AND node, and vice versa.
+            let mut phi = INF;
+            let mut delta = 0u32;
+            let mut best = 0;
+            let mut phi_2 = INF;
+            for (i, child) in children.iter().enumerate() {
+                let (c_phi, c_delta) = child.2.phi_delta(!or_node);
+                delta = delta.saturating_add(c_phi).min(INF);
+                if c_delta < phi {
+                    phi_2 = phi;
+                    phi = c_delta;
+                    best = i;
+                } else if c_delta < phi_2 {
+                    phi_2 = c_delta;
+                }
+            }
+            if phi >= th_phi || delta >= th_delta || self.limit_reached() {
+                let mut entry = Entry::from_phi_delta(phi, delta, or_node);
+                if entry.pn == 0 {
+                    let lens = children.iter().filter(|c| c.2.pn == 0).map(|c| c.2.len);
+                    // The attacker mates as soon as possible, and the defender delays it.
+                    entry.len = 1 + if or_node { lens.min() } else { lens.max() }.unwrap_or(0);
+                }
+                self.table.insert(key, entry);
+                return entry;
+            }
+            let (c_phi, _) = children[best].2.phi_delta(!or_node);
+            let child_th_phi = th_delta
+                .saturating_sub(delta)
+                .saturating_add(c_phi)
+                .min(INF);
+            let child_th_delta = th_phi.min(phi_2.saturating_add(1));
+            let mv = children[best].0;
+            position.do_move(mv, None);
+            children[best].2 = self.mid(position, !or_node, child_th_phi, child_th_delta, ply + 1);
+            position.undo_move(mv);
+        }
+    }
+
+    /// Follows the proven moves from the root.
+    fn principal_variation(&self, position: &mut Position) -> Vec<Move> {
+        let mut pv = Vec::new();
+        let mut or_node = true;
+        while pv.len() < MAX_PLY {
+            let moves = generate(position, or_node);
+            let proven = moves.into_iter().filter_map(|mv| {
+                position.do_move(mv, None);
+                let entry = self.entry(position.key());
+                position.undo_move(mv);
+                (entry.pn == 0).then_some((mv, entry.len))
+            });
+            let next = if or_node {
+                proven.min_by_key(|c| c.1)
+            } else {
+                proven.max_by_key(|c| c.1)
+            };
+            let Some((mv, _)) = next else {
+                break;
+            };
+            position.do_move(mv, None);
+            pv.push(mv);
+            or_node = !or_node;
+        }
+        for &mv in pv.iter().rev() {
+            position.undo_move(mv);
+        }
+        pv
+    }
+}
+
+/// Searches for a mate of the side to move by continuous checks with df-pn.
+pub fn mate_search(position: &mut Position, limits: &MateLimits, signals: &Signals) -> MateResult {
+    let clock = SystemClock;
+    let mut solver = Solver {
+        table: HashMap::new(),
+        limits,
+        signals,
+        start: clock.now(),
+        clock,
+        nodes: 0,
+        aborted: false,
+    };
+    let root = solver.mid(position, true, INF, INF, 0);
+    if root.pn == 0 {
+        MateResult::Mate(solver.principal_variation(position))
+    } else if root.dn == 0 && !solver.aborted {
+        MateResult::NoMate
+    } else {
+        MateResult::Unknown
+    }
+}
@@ -8,10 +8,11 @@ use crate::{
     change_bit, foreach_bb,
     position::Position,
     types::{
-        bit, get_from, get_move_type, get_pt, get_to, is_demise, make_move_drop, make_move_normal,
-        make_move_return, make_move_shoot, make_move_supply, Bitboard, ExtMove, Move, MoveType,
-        Piece, PieceType, Side, Square, MOVE_DEMISE, RANK_NB,
+        bit, get_capture, get_from, get_move_type, get_pt, get_to, is_demise, make_move_drop,
+        make_move_normal, make_move_return, make_move_shoot, make_move_supply, Bitboard, ExtMove,
+        Move, MoveType, Piece, PieceType, Side, Square, MOVE_DEMISE, RANK_NB,
     },
+    zobrist::HAND_PIECE_TYPES,
 };
 
 const MAX_MOVE: usize = 520;
@@ -21,6 +22,12 @@ pub struct MoveList {
     pub size: usize,
 }
 
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MoveList {
     pub fn new() -> Self {
         unsafe {
@@ -68,6 +75,10 @@ pub enum GenType {
     All,
     /// Pseudo-legal moves to evade.
     Evasion,
+    /// Pseudo-legal moves giving check when not in check.
+    Checks,
+    /// Pseudo-legal moves giving check without capturing when not in check.
+    QuietChecks,
     /// Legal moves.
     Legal,
 }
@@ -203,6 +214,127 @@ impl MoveList {
         self.generate_move_shoot(position, target);
     }
 
+    /// Generates normal moves giving check directly or by discovering an attack.
+    fn generate_check_normal(&mut self, position: &Position, target: Bitboard) {
+        let crown_sq = position.crown_sq(!position.side) as usize;
+        let blockers = position.check_blockers();
+        // A blocker gives a discovered check when it leaves the line to the crown.
+        let discovered = |sq: Square| {
+            if bit(blockers, sq as usize) == 1 {
+                !KG_BITBOARD.line_bb[sq as usize][crown_sq]
+            } else {
+                0
+            }
+        };
+        for pt in PieceType::iter() {
+            if pt == PieceType::None {
+                continue;
+            }
+            let check_bb = position.check_bb(pt);
+            foreach_bb!(position.pieces_pt_side(pt, position.side), sq, {
+                let movable_sq =
+                    KG_BITBOARD.movable_sq[pt.into_piece(position.side) as usize][sq as usize];
+                foreach_bb!(movable_sq & target & (check_bb | discovered(sq)), sq2, {
+                    self.push(make_move_normal(
+                        position.grid[sq2 as usize].split().0,
+                        sq,
+                        sq2,
+                    ));
+                });
+            });
+        }
+        let check_bb = position.check_bb(PieceType::Heavy);
+        foreach_bb!(position.heavy_attacks(position.side) & target, sq, {
+            let from = if position.side == Side::Black {
+                Square::from_usize(sq as usize - 16).unwrap()
+            } else {
+                Square::from_usize(sq as usize + 16).unwrap()
+            };
+            if bit(check_bb | discovered(from), sq as usize) == 1 {
+                self.push(make_move_normal(position.grid[sq as usize].pt(), from, sq));
+            }
+        });
+    }
+
+    /// Generates return moves giving check.
+    fn generate_check_return(&mut self, position: &Position) {
+        let start = self.size;
+        self.generate_move_return(position);
+        let mut end = start;
+        for i in start..self.size {
+            if position.gives_check(self.at(i).mv) {
+                self.moves[end].write(self.at(i).clone());
+                end += 1;
+            }
+        }
+        self.size = end;
+    }
+
+    /// Generates drop moves giving check.
+    fn generate_check_drop(&mut self, position: &Position) {
+        let mask = if position.side == Side::Black {
+            0x000000FFFFFFFFFF
+        } else {
+            0xFFFFFFFFFF000000
+        };
+        let bb = !position.pieces() & mask;
+
+        // Arrows never check.
+        for pt in [
+            PieceType::Light,
+            PieceType::Heavy,
+            PieceType::General,
+            PieceType::Knight,
+            PieceType::Archer0,
+        ] {
+            if position.count_hand(position.side, pt) != 0 {
+                foreach_bb!(bb & position.check_bb(pt), sq, {
+                    self.push(make_move_drop(pt, sq));
+                });
+            }
+        }
+        if position.count_hand(position.side, PieceType::Archer0) != 0 {
+            let arrow = position.count_hand(position.side, PieceType::Arrow);
+            if arrow >= 1 {
+                foreach_bb!(bb & position.check_bb(PieceType::Archer1), sq, {
+                    self.push(make_move_drop(PieceType::Archer1, sq));
+                });
+            }
+            if arrow >= 2 {
+                foreach_bb!(bb & position.check_bb(PieceType::Archer2), sq, {
+                    self.push(make_move_drop(PieceType::Archer2, sq));
+                });
+            }
+        }
+    }
+
+    /// Generates supply moves giving check.
+    fn generate_check_supply(&mut self, position: &Position) {
+        // An archer with an arrow already checks if it can, so only archers without one do.
+        if position.count_hand(position.side, PieceType::Arrow) != 0 {
+            let bb = position.pieces_pt_side(PieceType::Archer0, position.side)
+                & position.check_bb(PieceType::Archer1);
+            foreach_bb!(bb, sq, {
+                self.push(make_move_supply(sq));
+            });
+        }
+    }
+
+    /// Generates moves giving check.
+    /// Shoots never check since the archer loses an arrow and the arrow only blocks lines.
+    fn generate_checks(&mut self, position: &Position, quiet: bool) {
+        debug_assert!(position.checkers() == 0);
+        let target = if quiet {
+            !position.pieces()
+        } else {
+            !position.pieces_side(position.side)
+        };
+        self.generate_check_normal(position, target);
+        self.generate_check_return(position);
+        self.generate_check_drop(position);
+        self.generate_check_supply(position);
+    }
+
     /// Generates moves to evade.
     fn generate_evasion(&mut self, position: &Position) {
         // Demise.
@@ -254,10 +386,18 @@ impl MoveList {
                 attacks |= KG_BITBOARD.movable_sq[position.grid[c as usize] as usize][c as usize];
             }
         });
+        let target = if checkers_count != 1 {
+            0
+        } else if slide_check {
+            (1 << checker as usize) | KG_BITBOARD.between_bb[checker as usize][crown_sq as usize]
+        } else {
+            1 << checker as usize
+        };
+        // Moves of the crown to the target are generated below.
         let crown_evasion =
             KG_BITBOARD.movable_sq[position.grid[crown_sq as usize] as usize][crown_sq as usize];
         foreach_bb!(
-            crown_evasion & !attacks & !position.pieces_side(position.side),
+            crown_evasion & !attacks & !position.pieces_side(position.side) & !target,
             sq,
             {
                 let cap = position.grid[sq as usize].pt();
@@ -266,12 +406,6 @@ impl MoveList {
         );
 
         if checkers_count == 1 {
-            let target = if slide_check {
-                (1 << checker as usize)
-                    | KG_BITBOARD.between_bb[checker as usize][crown_sq as usize]
-            } else {
-                1 << checker as usize
-            };
             self.generate_move_normal(position, target);
             self.generate_move_shoot(position, target);
             self.generate_move_drop(position, target);
@@ -310,6 +444,8 @@ impl MoveList {
                 self.generate_non_captures(position);
             }
             GenType::Evasion => self.generate_evasion(position),
+            GenType::Checks => self.generate_checks(position, false),
+            GenType::QuietChecks => self.generate_checks(position, true),
             GenType::Legal => self.generate_legal(position),
         }
     }
@@ -320,7 +456,17 @@ impl Position {
         let typ = get_move_type(mv);
         let to = get_to(mv);
         if is_demise(mv) {
-            if self.demise[self.side as usize] >= 2 {
+            let demise = self.demise[self.side as usize];
+            if demise >= 2 {
+                return false;
+            }
+            // The crown passes to the prince, and then back to the king.
+            let pt = if demise == 0 {
+                PieceType::Prince
+            } else {
+                PieceType::King
+            };
+            if self.piece_list[self.side as usize][pt as usize][0] == Square::NONE {
                 return false;
             }
             if mv == MOVE_DEMISE {
@@ -333,15 +479,23 @@ impl Position {
                 if pt != PieceType::None && side == self.side {
                     return false;
                 }
+                // The move may come from another position.
+                if get_capture(mv) != pt {
+                    return false;
+                }
 
                 let from = get_from(mv);
                 let p = self.grid[from as usize];
                 if p == Piece::None || p.side() != self.side {
                     return false;
                 }
-                if p.pt() == PieceType::Heavy
-                    && (from as usize).abs_diff(to as usize) == RANK_NB * 2
-                {
+                // Heavies can also step two squares forward.
+                let two_step = if self.side == Side::Black {
+                    from as usize + RANK_NB * 2 == to as usize
+                } else {
+                    to as usize + RANK_NB * 2 == from as usize
+                };
+                if p.pt() == PieceType::Heavy && two_step {
                     let mid = (from as usize + to as usize) / 2;
                     if self.grid[mid] != Piece::None {
                         return false;
@@ -390,6 +544,9 @@ impl Position {
                 if pt != PieceType::None && side == self.side {
                     return false;
                 }
+                if get_capture(mv) != pt {
+                    return false;
+                }
 
                 let from = get_from(mv) as usize;
                 let (pt, side) = self.grid[from].split();
@@ -430,13 +587,22 @@ impl Position {
                     {
                         return false;
                     }
-                } else if self.count_hand(self.side, pt) == 0 {
+                } else if !HAND_PIECE_TYPES.contains(&pt) || self.count_hand(self.side, pt) == 0 {
                     return false;
                 }
 
                 if self.grid[to as usize] != Piece::None {
                     return false;
                 }
+                // Pieces are dropped in the own five ranks.
+                let mask: Bitboard = if self.side == Side::Black {
+                    0x000000FFFFFFFFFF
+                } else {
+                    0xFFFFFFFFFF000000
+                };
+                if bit(mask, to as usize) != 1 {
+                    return false;
+                }
             }
             MoveType::Supply => {
                 if self.count_hand(self.side, PieceType::Arrow) == 0 {
@@ -449,6 +615,12 @@ impl Position {
                 }
             }
         }
+        // `is_legal` expects an evasion when in check.
+        if self.checkers() != 0 {
+            let mut list = MoveList::new();
+            list.generate(self, GenType::Evasion);
+            return list.slice_mut(0).iter().any(|m| m.mv == mv);
+        }
         true
     }
 
@@ -458,7 +630,7 @@ impl Position {
                 let from = get_from(mv);
                 let to = get_to(mv);
                 let demise = self.demise[self.side as usize] + if is_demise(mv) { 1 } else { 0 };
-                let (blockers, crown_sq) = if demise % 2 == 0 {
+                let (blockers, crown_sq) = if demise.is_multiple_of(2) {
                     (
                         self.blockers_king(),
                         self.piece_list[self.side as usize][PieceType::King as usize][0],
@@ -480,4 +652,15 @@ impl Position {
             _ => true,
         }
     }
+
+    /// Returns whether the move is one of the legal moves.
+    /// Demise alone is not generated, so it is checked separately.
+    pub fn is_legal_move(&self, mv: Move) -> bool {
+        if mv == MOVE_DEMISE {
+            return self.is_pseudo_legal(mv) && self.is_legal(mv);
+        }
+        let mut list = MoveList::new();
+        list.generate(self, GenType::Legal);
+        list.slice_mut(0).iter().any(|m| m.mv == mv)
+    }
 }
@@ -3,16 +3,24 @@ use crate::movegen::GenType;
 use super::{
     movegen::MoveList,
     position::Position,
-    types::{get_capture, ExtMove, Move, PIECE_TYPE_NB},
+    types::{
+        get_capture, get_from, get_move_type, get_pt, get_to, is_demise, ExtMove, Move, MoveType,
+        PieceType, MAX_PLY, MOVE_NONE, PIECE_NB, PIECE_TYPE_NB, SIDE_NB, SQUARE_NB,
+    },
 };
 
 enum Stage {
+    MainTT,
     CapturesInit,
-    Captures,
+    GoodCaptures,
+    Refutations,
     NonCapturesInit,
     NonCaptures,
+    BadCaptures,
+    EvasionTT,
     EvasionInit,
     Evasion,
+    QuietTT,
     QuietInit,
     Quiet,
 }
@@ -21,6 +29,105 @@ pub struct MovePicker {
     cur: usize,
     moves: MoveList,
     stage: Stage,
+    tt_move: Move,
+    /// Killer moves and the countermove, tried after the captures.
+    refutations: [Move; 3],
+    /// Captures losing material are kept before this index until the quiet moves are tried.
+    bad_end: usize,
+}
+
+/// Maximum absolute value of the history.
+const HISTORY_MAX: i32 = 16384;
+
+/// Statistics of quiet moves which caused beta cutoffs.
+pub struct History {
+    /// Two killer moves per ply.
+    killers: [[Move; 2]; MAX_PLY + 1],
+    /// Indexed by the side, the type of the moved piece and the target square.
+    butterfly: [[[i32; SQUARE_NB]; PIECE_TYPE_NB]; SIDE_NB],
+    /// Move refuting the previous move, indexed by the piece and the target square of it.
+    countermoves: [[Move; SQUARE_NB]; PIECE_NB],
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            killers: [[MOVE_NONE; 2]; MAX_PLY + 1],
+            butterfly: [[[0; SQUARE_NB]; PIECE_TYPE_NB]; SIDE_NB],
+            countermoves: [[MOVE_NONE; SQUARE_NB]; PIECE_NB],
+        }
+    }
+}
+
+/// Returns the type of the piece moved, dropped or supplied by the move.
+fn moved_piece_type(position: &Position, mv: Move) -> PieceType {
+    match get_move_type(mv) {
+        MoveType::Normal | MoveType::Return | MoveType::Shoot => {
+            position.grid[get_from(mv) as usize].pt()
+        }
+        MoveType::Drop => get_pt(mv),
+        MoveType::Supply => PieceType::Arrow,
+    }
+}
+
+/// Returns true if the move does not capture.
+pub fn is_quiet(mv: Move) -> bool {
+    get_capture(mv) == PieceType::None
+}
+
+impl History {
+    pub fn killers(&self, ply: usize) -> [Move; 2] {
+        self.killers[ply]
+    }
+
+    /// Returns the countermove of `prev`, which was played to reach the position.
+    pub fn countermove(&self, position: &Position, prev: Move) -> Move {
+        if prev == MOVE_NONE {
+            return MOVE_NONE;
+        }
+        let to = get_to(prev) as usize;
+        self.countermoves[position.grid[to] as usize][to]
+    }
+
+    /// Returns the history score of the quiet move.
+    pub fn score(&self, position: &Position, mv: Move) -> i32 {
+        let pt = moved_piece_type(position, mv);
+        self.butterfly[position.side as usize][pt as usize][get_to(mv) as usize]
+    }
+
+    fn add(&mut self, position: &Position, mv: Move, bonus: i32) {
+        let pt = moved_piece_type(position, mv);
+        let entry = &mut self.butterfly[position.side as usize][pt as usize][get_to(mv) as usize];
+        // The entry decays as it gets close to the maximum.
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+
+    /// Updates the statistics with a quiet move which caused a beta cutoff.
+    /// `quiets` are the quiet moves searched before it.
+    pub fn update(
+        &mut self,
+        position: &Position,
+        ply: usize,
+        prev: Move,
+        mv: Move,
+        quiets: &[Move],
+        depth: usize,
+    ) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != mv {
+            killers[1] = killers[0];
+            killers[0] = mv;
+        }
+        if prev != MOVE_NONE {
+            let to = get_to(prev) as usize;
+            self.countermoves[position.grid[to] as usize][to] = mv;
+        }
+        let bonus = (depth * depth).min(HISTORY_MAX as usize / 16) as i32;
+        self.add(position, mv, bonus);
+        for &quiet in quiets {
+            self.add(position, quiet, -bonus);
+        }
+    }
 }
 
 const PIECE_VALUES: [i32; PIECE_TYPE_NB] = [0, 100, 200, 800, 600, 400, 400, 400, 400, 800, 1200];
@@ -33,9 +140,9 @@ fn score_captures(moves: &mut [ExtMove]) {
     }
 }
 
-fn score_noncaptures(moves: &mut [ExtMove]) {
+fn score_noncaptures(moves: &mut [ExtMove], position: &Position, history: &History) {
     for ext_move in moves {
-        ext_move.score = 0;
+        ext_move.score = history.score(position, ext_move.mv);
     }
 }
 
@@ -61,52 +168,136 @@ fn select_best(moves: &mut [ExtMove], cur: &mut usize) -> Option<Move> {
 }
 
 impl MovePicker {
-    pub fn new(position: &Position) -> Self {
+    pub fn new(position: &Position, tt_move: Move, killers: [Move; 2], countermove: Move) -> Self {
+        let tt_move = if tt_move != MOVE_NONE && position.is_pseudo_legal(tt_move) {
+            tt_move
+        } else {
+            MOVE_NONE
+        };
+        let mut refutations = [killers[0], killers[1], countermove];
+        for i in 0..refutations.len() {
+            let mv = refutations[i];
+            // Demise is only searched as an evasion.
+            if mv == MOVE_NONE
+                || mv == tt_move
+                || !is_quiet(mv)
+                || is_demise(mv)
+                || refutations[..i].contains(&mv)
+                || !position.is_pseudo_legal(mv)
+            {
+                refutations[i] = MOVE_NONE;
+            }
+        }
         MovePicker {
+            refutations,
+            bad_end: 0,
             cur: 0,
             moves: MoveList::new(),
             stage: if position.checkers() != 0 {
-                Stage::EvasionInit
+                Stage::EvasionTT
             } else {
-                Stage::CapturesInit
+                Stage::MainTT
             },
+            tt_move,
         }
     }
 
-    pub fn qsearch() -> Self {
+    pub fn qsearch(position: &Position, tt_move: Move) -> Self {
+        // Only captures and evasions are searched in qsearch.
+        let tt_move = if tt_move != MOVE_NONE
+            && (position.checkers() != 0 || get_capture(tt_move) != PieceType::None)
+            && position.is_pseudo_legal(tt_move)
+        {
+            tt_move
+        } else {
+            MOVE_NONE
+        };
         MovePicker {
             cur: 0,
             moves: MoveList::new(),
-            stage: Stage::QuietInit,
+            stage: Stage::QuietTT,
+            tt_move,
+            refutations: [MOVE_NONE; 3],
+            bad_end: 0,
         }
     }
 
-    pub fn next_move(&mut self, position: &Position) -> Option<Move> {
+    /// Returns the next move of the list, skipping the TT move.
+    fn next_from_list(&mut self) -> Option<Move> {
+        while self.cur < self.moves.size {
+            let mv = self.moves.at(self.cur).mv;
+            self.cur += 1;
+            if mv != self.tt_move {
+                return Some(mv);
+            }
+        }
+        None
+    }
+
+    /// Returns the next move. `history` orders the quiet moves.
+    pub fn next_move(&mut self, position: &Position, history: &History) -> Option<Move> {
         loop {
             match self.stage {
+                Stage::MainTT | Stage::EvasionTT | Stage::QuietTT => {
+                    self.stage = match self.stage {
+                        Stage::MainTT => Stage::CapturesInit,
+                        Stage::EvasionTT => Stage::EvasionInit,
+                        _ => Stage::QuietInit,
+                    };
+                    if self.tt_move != MOVE_NONE {
+                        return Some(self.tt_move);
+                    }
+                }
                 Stage::CapturesInit => {
                     self.moves.generate(position, GenType::Captures);
                     score_captures(self.moves.slice_mut(0));
-                    self.stage = Stage::Captures;
+                    self.stage = Stage::GoodCaptures;
                 }
-                Stage::Captures => {
-                    if let Some(mv) = select_best(self.moves.slice_mut(0), &mut self.cur) {
-                        return Some(mv);
+                Stage::GoodCaptures => {
+                    while let Some(mv) = select_best(self.moves.slice_mut(0), &mut self.cur) {
+                        if mv == self.tt_move {
+                            continue;
+                        }
+                        if position.see(mv, 0) {
+                            return Some(mv);
+                        }
+                        // Move the bad capture to the front, which is already picked.
+                        self.moves.slice_mut(0).swap(self.bad_end, self.cur - 1);
+                        self.bad_end += 1;
                     }
                     self.cur = 0;
+                    self.stage = Stage::Refutations;
+                }
+                Stage::Refutations => {
+                    while self.cur < self.refutations.len() {
+                        let mv = self.refutations[self.cur];
+                        self.cur += 1;
+                        if mv != MOVE_NONE {
+                            return Some(mv);
+                        }
+                    }
                     self.stage = Stage::NonCapturesInit;
                 }
                 Stage::NonCapturesInit => {
-                    self.moves.size = 0;
+                    self.moves.size = self.bad_end;
                     self.moves.generate(position, GenType::NonCaptures);
-                    score_noncaptures(self.moves.slice_mut(0));
+                    score_noncaptures(self.moves.slice_mut(self.bad_end), position, history);
+                    self.cur = self.bad_end;
                     self.stage = Stage::NonCaptures;
                 }
                 Stage::NonCaptures => {
-                    if self.cur < self.moves.size {
-                        let mv = self.moves.at(self.cur).mv;
+                    while let Some(mv) = select_best(self.moves.slice_mut(0), &mut self.cur) {
+                        if mv != self.tt_move && !self.refutations.contains(&mv) {
+                            return Some(mv);
+                        }
+                    }
+                    self.cur = 0;
+                    self.stage = Stage::BadCaptures;
+                }
+                Stage::BadCaptures => {
+                    if self.cur < self.bad_end {
                         self.cur += 1;
-                        return Some(mv);
+                        return Some(self.moves.at(self.cur - 1).mv);
                     }
                     break;
                 }
@@ -115,9 +306,7 @@ impl MovePicker {
                     self.stage = Stage::Evasion;
                 }
                 Stage::Evasion => {
-                    if self.cur < self.moves.size {
-                        let mv = self.moves.at(self.cur).mv;
-                        self.cur += 1;
+                    if let Some(mv) = self.next_from_list() {
                         return Some(mv);
                     }
                     break;
@@ -131,9 +320,7 @@ impl MovePicker {
                     self.stage = Stage::Quiet;
                 }
                 Stage::Quiet => {
-                    if self.cur < self.moves.size {
-                        let mv = self.moves.at(self.cur).mv;
-                        self.cur += 1;
+                    if let Some(mv) = self.next_from_list() {
                         return Some(mv);
                     }
                     break;
@@ -0,0 +1,201 @@
+use num_traits::FromPrimitive;
+
+use crate::{
+    movegen::{GenType, MoveList},
+    position::Position,
+    types::{
+        get_capture, get_from, get_move_type, get_pt, get_to, is_demise, Move, MoveError, MoveType,
+        PieceType, Side, Square, MOVE_DEMISE, RANK_NB,
+    },
+};
+
+/// Returns the square in lowercase such as `c4`.
+fn square_name(sq: Square) -> String {
+    sq.to_string().to_lowercase()
+}
+
+fn read_square(s: &str) -> Option<Square> {
+    match s.as_bytes() {
+        &[x @ b'a'..=b'h', y @ b'1'..=b'8'] => {
+            Square::from_usize((y - b'1') as usize * RANK_NB + (x - b'a') as usize)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the type of the piece which makes the move.
+fn moving_pt(position: &Position, mv: Move) -> PieceType {
+    match get_move_type(mv) {
+        MoveType::Drop => get_pt(mv),
+        MoveType::Supply => PieceType::Arrow,
+        _ => position.grid[get_from(mv) as usize].pt(),
+    }
+}
+
+fn legal_moves(position: &Position) -> Vec<Move> {
+    let mut list = MoveList::new();
+    list.generate(position, GenType::Legal);
+    (0..list.size).map(|i| list.at(i).mv).collect()
+}
+
+impl Position {
+    /// Returns the move in the descriptive notation.
+    ///
+    /// | Move             | Notation        |
+    /// |------------------|-----------------|
+    /// | Normal           | `Hc4`, `Gxd2`   |
+    /// | Shoot            | `Bxa8S`, `Bc3S` |
+    /// | Return           | `R^e3`          |
+    /// | Drop             | `L*c4`          |
+    /// | Supply           | `R*^c4`         |
+    /// | Demise alone     | `D`             |
+    ///
+    /// The file, the rank or the square the piece moves from follows the piece letter if
+    /// another piece of the type can make the same kind of move, such as `Lbc3`.
+    /// `D` is added to a move with demise, and `+` to a move giving check.
+    pub fn notation(&self, mv: Move) -> String {
+        if mv == MOVE_DEMISE {
+            return "D".to_string();
+        }
+        let typ = get_move_type(mv);
+        let to = get_to(mv);
+        let pt = moving_pt(self, mv);
+        let mut s = pt.into_piece(Side::Black).to_string();
+        match typ {
+            MoveType::Drop => s.push('*'),
+            MoveType::Supply => s.push_str("*^"),
+            _ => {
+                s += &self.disambiguation(mv);
+                if typ == MoveType::Return {
+                    s.push('^');
+                } else if get_capture(mv) != PieceType::None {
+                    s.push('x');
+                }
+            }
+        }
+        s += &square_name(to);
+        if typ == MoveType::Shoot {
+            s.push('S');
+        }
+        if is_demise(mv) {
+            s.push('D');
+        }
+        if self.gives_check(mv) {
+            s.push('+');
+        }
+        s
+    }
+
+    /// Returns the part of the square the piece moves from needed to tell it from the others.
+    fn disambiguation(&self, mv: Move) -> String {
+        let from = get_from(mv);
+        let others: Vec<Square> = legal_moves(self)
+            .into_iter()
+            .filter(|&m| {
+                m != mv
+                    && get_from(m) != from
+                    && get_to(m) == get_to(mv)
+                    && get_move_type(m) == get_move_type(mv)
+                    && is_demise(m) == is_demise(mv)
+                    && moving_pt(self, m) == moving_pt(self, mv)
+            })
+            .map(get_from)
+            .collect();
+        let name = square_name(from);
+        if others.is_empty() {
+            String::new()
+        } else if others
+            .iter()
+            .all(|&sq| sq as usize % RANK_NB != from as usize % RANK_NB)
+        {
+            name[..1].to_string()
+        } else if others
+            .iter()
+            .all(|&sq| sq as usize / RANK_NB != from as usize / RANK_NB)
+        {
+            name[1..].to_string()
+        } else {
+            name
+        }
+    }
+
+    /// Reads a legal move in the descriptive notation written by `notation`.
+    /// The check marker is optional, and the square the piece moves from may be given fully.
+    pub fn read_notation(&self, notation: &str) -> Result<Move, MoveError> {
+        let invalid = || MoveError::InvalidNotation(notation.to_string());
+        let s = notation.trim_end_matches(['+', '#']);
+        if s == "D" {
+            return if self.is_legal_move(MOVE_DEMISE) {
+                Ok(MOVE_DEMISE)
+            } else {
+                Err(MoveError::Illegal(notation.to_string()))
+            };
+        }
+        let (s, demise) = match s.strip_suffix('D') {
+            Some(s) => (s, true),
+            None => (s, false),
+        };
+        let (s, shoot) = match s.strip_suffix('S') {
+            Some(s) => (s, true),
+            None => (s, false),
+        };
+        if !s.is_ascii() || s.len() < 3 {
+            return Err(invalid());
+        }
+        let (piece, rest) = s.split_at(1);
+        let pt = PieceType::from_char(piece.as_bytes()[0]);
+        if pt == PieceType::None || piece != piece.to_uppercase() {
+            return Err(invalid());
+        }
+        let (body, to) = rest.split_at(rest.len() - 2);
+        let to = read_square(to).ok_or_else(invalid)?;
+
+        let (typ, capture, from) = match body {
+            "*" => (MoveType::Drop, None, ""),
+            "*^" => (MoveType::Supply, None, ""),
+            _ => {
+                if let Some(from) = body.strip_suffix('^') {
+                    (MoveType::Return, None, from)
+                } else if let Some(from) = body.strip_suffix('x') {
+                    (MoveType::Normal, Some(true), from)
+                } else {
+                    (MoveType::Normal, Some(false), body)
+                }
+            }
+        };
+        let typ = match (typ, shoot) {
+            (MoveType::Normal, true) => MoveType::Shoot,
+            (_, true) => return Err(invalid()),
+            (typ, false) => typ,
+        };
+        // The square the piece moves from is given by its file, its rank or both.
+        let (file, rank) = match from.as_bytes() {
+            [] => (None, None),
+            &[x @ b'a'..=b'h'] => (Some((x - b'a') as usize), None),
+            &[y @ b'1'..=b'8'] => (None, Some((y - b'1') as usize)),
+            &[x @ b'a'..=b'h', y @ b'1'..=b'8'] => {
+                (Some((x - b'a') as usize), Some((y - b'1') as usize))
+            }
+            _ => return Err(invalid()),
+        };
+
+        let candidates: Vec<Move> = legal_moves(self)
+            .into_iter()
+            .filter(|&m| {
+                let from = get_from(m) as usize;
+                get_to(m) == to
+                    && get_move_type(m) == typ
+                    && is_demise(m) == demise
+                    && moving_pt(self, m) == pt
+                    && capture.is_none_or(|c| c == (get_capture(m) != PieceType::None))
+                    && file.is_none_or(|x| from % RANK_NB == x)
+                    && rank.is_none_or(|y| from / RANK_NB == y)
+            })
+            .collect();
+        match candidates[..] {
+            [mv] => Ok(mv),
+            [] => Err(MoveError::Illegal(notation.to_string())),
+            _ => Err(MoveError::Ambiguous(notation.to_string())),
+        }
+    }
+}
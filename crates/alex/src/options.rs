@@ -0,0 +1,286 @@
+use std::fmt;
+
+use crate::{
+    search::{RepetitionRule, SearchOptions, MAX_SKILL_LEVEL},
+    tt::DEFAULT_HASH_SIZE,
+};
+
+/// Type and default value of an option.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OptionType {
+    Check(bool),
+    Spin {
+        default: u64,
+        min: u64,
+        max: u64,
+    },
+    String(&'static str),
+    /// One of the values in `vars`.
+    Combo {
+        default: &'static str,
+        vars: &'static [&'static str],
+    },
+}
+
+/// Definition of an option advertised to the GUI.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OptionDef {
+    pub name: &'static str,
+    pub ty: OptionType,
+}
+
+impl fmt::Display for OptionDef {
+    /// Formats the definition as an `option` line of UMI.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+        match self.ty {
+            OptionType::Check(default) => write!(f, "check default {}", default),
+            OptionType::Spin { default, min, max } => {
+                write!(f, "spin default {} min {} max {}", default, min, max)
+            }
+            OptionType::String("") => write!(f, "string default <empty>"),
+            OptionType::String(default) => write!(f, "string default {}", default),
+            OptionType::Combo { default, vars } => {
+                write!(f, "combo default {}", default)?;
+                for var in vars {
+                    write!(f, " var {}", var)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub const OPTION_HASH: &str = "Hash";
+pub const OPTION_THREADS: &str = "Threads";
+pub const OPTION_MULTI_PV: &str = "MultiPV";
+pub const OPTION_PONDER: &str = "Ponder";
+pub const OPTION_MOVE_OVERHEAD: &str = "MoveOverhead";
+pub const OPTION_EVAL_FILE: &str = "EvalFile";
+pub const OPTION_BOOK_FILE: &str = "BookFile";
+pub const OPTION_SKILL_LEVEL: &str = "SkillLevel";
+pub const OPTION_DEBUG_LOG_FILE: &str = "DebugLogFile";
+pub const OPTION_REPETITION_RULE: &str = "RepetitionRule";
+
+/// Options of the engine.
+pub const OPTIONS: [OptionDef; 10] = [
+    OptionDef {
+        name: OPTION_HASH,
+        ty: OptionType::Spin {
+            default: DEFAULT_HASH_SIZE as u64,
+            min: 1,
+            max: 65536,
+        },
+    },
+    OptionDef {
+        name: OPTION_THREADS,
+        ty: OptionType::Spin {
+            default: 1,
+            min: 1,
+            max: 256,
+        },
+    },
+    OptionDef {
+        name: OPTION_MULTI_PV,
+        ty: OptionType::Spin {
+            default: 1,
+            min: 1,
+            max: 256,
+        },
+    },
+    OptionDef {
+        name: OPTION_PONDER,
+        ty: OptionType::Check(true),
+    },
+    OptionDef {
+        name: OPTION_MOVE_OVERHEAD,
+        ty: OptionType::Spin {
+            default: 100,
+            min: 0,
+            max: 5000,
+        },
+    },
+    OptionDef {
+        name: OPTION_EVAL_FILE,
+        ty: OptionType::String(""),
+    },
+    OptionDef {
+        name: OPTION_BOOK_FILE,
+        ty: OptionType::String(""),
+    },
+    OptionDef {
+        name: OPTION_SKILL_LEVEL,
+        ty: OptionType::Spin {
+            default: MAX_SKILL_LEVEL as u64,
+            min: 0,
+            max: MAX_SKILL_LEVEL as u64,
+        },
+    },
+    OptionDef {
+        name: OPTION_DEBUG_LOG_FILE,
+        ty: OptionType::String(""),
+    },
+    OptionDef {
+        name: OPTION_REPETITION_RULE,
+        ty: OptionType::Combo {
+            default: RepetitionRule::NAMES[0],
+            vars: &RepetitionRule::NAMES,
+        },
+    },
+];
+
+/// Error of setting an option.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum OptionError {
+    UnknownName(String),
+    MissingValue(&'static str),
+    InvalidValue(&'static str, String),
+    OutOfRange(&'static str, u64, u64, u64),
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionError::UnknownName(name) => write!(f, "unknown option: {}", name),
+            OptionError::MissingValue(name) => write!(f, "missing value of {}", name),
+            OptionError::InvalidValue(name, value) => {
+                write!(f, "invalid value of {}: {}", name, value)
+            }
+            OptionError::OutOfRange(name, value, min, max) => {
+                write!(f, "{} must be between {} and {}: {}", name, min, max, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+/// Value of an option after validation.
+enum OptionValue {
+    Check(bool),
+    Spin(u64),
+    String(String),
+}
+
+/// Current values of the options.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EngineOptions {
+    /// Size of the transposition table in megabytes.
+    pub hash: usize,
+    pub threads: usize,
+    pub multi_pv: usize,
+    pub ponder: bool,
+    /// Time in milliseconds kept for the communication with the GUI.
+    pub move_overhead: u64,
+    /// Path of the parameters of the evaluation. Empty to use the default.
+    pub eval_file: String,
+    /// Path of the opening book. Empty to disable the book.
+    pub book_file: String,
+    pub skill_level: usize,
+    /// Path of the file to log the communication. Empty to disable the log.
+    pub debug_log_file: String,
+    pub rule: RepetitionRule,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        let mut options = EngineOptions {
+            hash: 0,
+            threads: 0,
+            multi_pv: 0,
+            ponder: false,
+            move_overhead: 0,
+            eval_file: String::new(),
+            book_file: String::new(),
+            skill_level: 0,
+            debug_log_file: String::new(),
+            rule: RepetitionRule::default(),
+        };
+        for def in OPTIONS {
+            let value = match def.ty {
+                OptionType::Check(default) => OptionValue::Check(default),
+                OptionType::Spin { default, .. } => OptionValue::Spin(default),
+                OptionType::String(default) | OptionType::Combo { default, .. } => {
+                    OptionValue::String(default.to_string())
+                }
+            };
+            options.assign(def.name, value);
+        }
+        options
+    }
+}
+
+impl EngineOptions {
+    /// Sets the option `name` to `value` after validating it.
+    /// Names are case-insensitive, and `value` is `None` if it is not given.
+    /// Returns the canonical name of the option.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<&'static str, OptionError> {
+        let def = OPTIONS
+            .iter()
+            .find(|def| def.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| OptionError::UnknownName(name.to_string()))?;
+        let value = match def.ty {
+            OptionType::Check(_) => match value {
+                Some("true") => OptionValue::Check(true),
+                Some("false") => OptionValue::Check(false),
+                Some(value) => return Err(OptionError::InvalidValue(def.name, value.to_string())),
+                None => return Err(OptionError::MissingValue(def.name)),
+            },
+            OptionType::Spin { min, max, .. } => {
+                let value = value.ok_or(OptionError::MissingValue(def.name))?;
+                let n = value
+                    .parse::<u64>()
+                    .map_err(|_| OptionError::InvalidValue(def.name, value.to_string()))?;
+                if n < min || max < n {
+                    return Err(OptionError::OutOfRange(def.name, n, min, max));
+                }
+                OptionValue::Spin(n)
+            }
+            // `<empty>` is the conventional way to clear a string.
+            OptionType::String(_) => match value {
+                None | Some("<empty>") => OptionValue::String(String::new()),
+                Some(value) => OptionValue::String(value.to_string()),
+            },
+            OptionType::Combo { vars, .. } => {
+                let value = value.ok_or(OptionError::MissingValue(def.name))?;
+                let var = vars
+                    .iter()
+                    .find(|var| var.eq_ignore_ascii_case(value))
+                    .ok_or_else(|| OptionError::InvalidValue(def.name, value.to_string()))?;
+                OptionValue::String(var.to_string())
+            }
+        };
+        self.assign(def.name, value);
+        Ok(def.name)
+    }
+
+    fn assign(&mut self, name: &str, value: OptionValue) {
+        match (name, value) {
+            (OPTION_HASH, OptionValue::Spin(n)) => self.hash = n as usize,
+            (OPTION_THREADS, OptionValue::Spin(n)) => self.threads = n as usize,
+            (OPTION_MULTI_PV, OptionValue::Spin(n)) => self.multi_pv = n as usize,
+            (OPTION_PONDER, OptionValue::Check(b)) => self.ponder = b,
+            (OPTION_MOVE_OVERHEAD, OptionValue::Spin(n)) => self.move_overhead = n,
+            (OPTION_EVAL_FILE, OptionValue::String(s)) => self.eval_file = s,
+            (OPTION_BOOK_FILE, OptionValue::String(s)) => self.book_file = s,
+            (OPTION_SKILL_LEVEL, OptionValue::Spin(n)) => self.skill_level = n as usize,
+            (OPTION_DEBUG_LOG_FILE, OptionValue::String(s)) => self.debug_log_file = s,
+            (OPTION_REPETITION_RULE, OptionValue::String(s)) => {
+                self.rule = RepetitionRule::from_name(&s).unwrap()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the options used by the search.
+    pub fn search_options(&self) -> SearchOptions {
+        SearchOptions {
+            threads: self.threads,
+            multi_pv: self.multi_pv,
+            move_overhead: self.move_overhead,
+            skill_level: self.skill_level,
+            rule: self.rule,
+            ..Default::default()
+        }
+    }
+}
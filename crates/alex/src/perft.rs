@@ -1,30 +1,109 @@
+use std::ops::AddAssign;
+
 use crate::{
     movegen::{GenType, MoveList},
     position::Position,
-    types::move_to_mfen,
+    types::{get_capture, get_move_type, is_demise, Move, MoveType, PieceType},
 };
 
-pub fn perft(position: &mut Position, depth: usize, debug: bool) -> usize {
+/// Counts of the leaf moves by their kind.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PerftStats {
+    pub nodes: u64,
+    pub captures: u64,
+    pub drops: u64,
+    pub shoots: u64,
+    pub returns: u64,
+    pub supplies: u64,
+    pub demises: u64,
+    pub checks: u64,
+}
+
+impl AddAssign for PerftStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.nodes += rhs.nodes;
+        self.captures += rhs.captures;
+        self.drops += rhs.drops;
+        self.shoots += rhs.shoots;
+        self.returns += rhs.returns;
+        self.supplies += rhs.supplies;
+        self.demises += rhs.demises;
+        self.checks += rhs.checks;
+    }
+}
+
+fn legal_moves(position: &Position) -> Vec<Move> {
+    let mut moves = MoveList::new();
+    moves.generate(position, GenType::Legal);
+    (0..moves.size).map(|i| moves.at(i).mv).collect()
+}
+
+/// Counts the leaf nodes. The moves at the last ply are counted without playing them.
+pub fn perft(position: &mut Position, depth: usize) -> u64 {
     if depth == 0 {
         return 1;
     }
+    let moves = legal_moves(position);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
 
     let mut nodes = 0;
-
-    let mut moves = MoveList::new();
-    moves.generate(position, GenType::Legal);
-    for i in 0..moves.size {
-        let mv = moves.at(i).mv;
-        if debug {
-            for _ in 0..depth - 1 {
-                print!("  ");
-            }
-            println!("{}", move_to_mfen(mv, position.side));
-        }
+    for mv in moves {
         position.do_move(mv, None);
-        nodes += perft(position, depth - 1, debug);
+        nodes += perft(position, depth - 1);
         position.undo_move(mv);
     }
-
     nodes
 }
+
+/// Returns the count of leaf nodes after each legal move.
+pub fn perft_divide(position: &mut Position, depth: usize) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    legal_moves(position)
+        .into_iter()
+        .map(|mv| {
+            position.do_move(mv, None);
+            let nodes = perft(position, depth - 1);
+            position.undo_move(mv);
+            (mv, nodes)
+        })
+        .collect()
+}
+
+/// Counts the leaf nodes by the kind of the last move.
+pub fn perft_stats(position: &mut Position, depth: usize) -> PerftStats {
+    let mut stats = PerftStats::default();
+    if depth == 0 {
+        stats.nodes = 1;
+        return stats;
+    }
+    for mv in legal_moves(position) {
+        if depth > 1 {
+            position.do_move(mv, None);
+            stats += perft_stats(position, depth - 1);
+            position.undo_move(mv);
+            continue;
+        }
+        stats.nodes += 1;
+        if get_capture(mv) != PieceType::None {
+            stats.captures += 1;
+        }
+        match get_move_type(mv) {
+            MoveType::Normal => {}
+            MoveType::Drop => stats.drops += 1,
+            MoveType::Shoot => stats.shoots += 1,
+            MoveType::Return => stats.returns += 1,
+            MoveType::Supply => stats.supplies += 1,
+        }
+        if is_demise(mv) {
+            stats.demises += 1;
+        }
+        if position.gives_check(mv) {
+            stats.checks += 1;
+        }
+    }
+    stats
+}
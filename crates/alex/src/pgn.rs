@@ -0,0 +1,369 @@
+use std::str::FromStr;
+
+use crate::{
+    game::{DrawReason, GameResult, WinReason},
+    kifu::{single_line, Kifu, KifuError, KifuMove},
+    position::Position,
+    types::{move_to_mfen, MoveError, Side, Value, MOVE_DEMISE},
+};
+
+/// Writes a time in milliseconds as `H:MM:SS.mmm`.
+fn clock_to_string(time: u64) -> String {
+    let secs = time / 1000;
+    let ms = time % 1000;
+    let clock = format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    if ms == 0 {
+        clock
+    } else {
+        format!("{}.{:03}", clock, ms)
+    }
+}
+
+fn read_clock(s: &str) -> Option<u64> {
+    let (s, ms) = match s.split_once('.') {
+        Some((s, frac)) if !frac.is_empty() && frac.len() <= 3 => (
+            s,
+            frac.parse::<u64>().ok()? * 10u64.pow(3 - frac.len() as u32),
+        ),
+        Some(_) => return None,
+        None => (s, 0),
+    };
+    let mut secs = 0;
+    for part in s.split(':') {
+        secs = secs * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(secs * 1000 + ms)
+}
+
+/// Writes a value as the count of lights, such as `-0.35`.
+fn eval_to_string(eval: Value) -> String {
+    let sign = if eval < 0 { "-" } else { "" };
+    let abs = eval.unsigned_abs();
+    format!("{}{}.{:02}", sign, abs / 100, abs % 100)
+}
+
+fn read_eval(s: &str) -> Option<Value> {
+    let eval = s.parse::<f64>().ok()? * 100.0;
+    (eval.abs() <= Value::MAX as f64).then(|| eval.round() as Value)
+}
+
+/// Escapes a tag value.
+fn quote(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        single_line(s).replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+fn unquote(s: &str) -> Option<String> {
+    let s = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut value = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?),
+            '"' => return None,
+            _ => value.push(c),
+        }
+    }
+    Some(value)
+}
+
+/// Element of the movetext.
+enum Token {
+    Word(String),
+    Comment(String),
+}
+
+/// Splits the movetext into words and comments with their line numbers.
+fn tokenize(lines: &[(usize, &str)]) -> Result<Vec<(usize, Token)>, KifuError> {
+    let mut tokens = Vec::new();
+    let mut comment: Option<(usize, String)> = None;
+    for &(i, line) in lines {
+        let mut rest = line;
+        while !rest.is_empty() {
+            if let Some((start, text)) = comment.as_mut() {
+                match rest.split_once('}') {
+                    Some((body, after)) => {
+                        text.push_str(body);
+                        tokens.push((*start, Token::Comment(text.clone())));
+                        comment = None;
+                        rest = after;
+                    }
+                    None => {
+                        text.push_str(rest);
+                        text.push(' ');
+                        rest = "";
+                    }
+                }
+                continue;
+            }
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix('{') {
+                comment = Some((i, String::new()));
+                rest = after;
+            } else if rest.starts_with(';') {
+                // The rest of the line is a comment.
+                let text = rest[1..].trim().to_string();
+                tokens.push((i, Token::Comment(text)));
+                rest = "";
+            } else if !rest.is_empty() {
+                let end = rest.find([' ', '\t', '{', ';']).unwrap_or(rest.len());
+                tokens.push((i, Token::Word(rest[..end].to_string())));
+                rest = &rest[end..];
+            }
+        }
+    }
+    match comment {
+        Some((i, text)) => Err(KifuError::Syntax(i, format!("{{{}", text))),
+        None => Ok(tokens),
+    }
+}
+
+/// Reads the clock and the evaluation commands in a comment into the move.
+fn read_comment(m: &mut KifuMove, side: Side, text: &str) -> Option<()> {
+    let mut rest = text;
+    let mut comment = String::new();
+    while let Some(start) = rest.find("[%") {
+        comment.push_str(&rest[..start]);
+        let end = rest[start..].find(']')? + start;
+        let mut command = rest[start + 2..end].split_whitespace();
+        let name = command.next()?;
+        let value = command.next()?;
+        match name {
+            "emt" => m.time = Some(read_clock(value)?),
+            "eval" => {
+                let eval = read_eval(value)?;
+                m.eval = Some(if side == Side::White { eval } else { -eval });
+            }
+            _ => comment.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    comment.push_str(rest);
+    let comment = comment.trim();
+    if !comment.is_empty() {
+        m.comment = Some(comment.to_string());
+    }
+    Some(())
+}
+
+fn result_to_pgn(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Ongoing => "*",
+        GameResult::Win(Side::White, _) => "1-0",
+        GameResult::Win(Side::Black, _) => "0-1",
+        GameResult::Draw(_) => "1/2-1/2",
+    }
+}
+
+impl Kifu {
+    /// Writes the game in PGN.
+    ///
+    /// Moves are written in the notation of `Position::notation`.
+    /// Black moves first, and the move numbers count the moves of Black.
+    /// Results and evaluations are from the point of view of White as in chess.
+    /// Besides the seven tags of PGN, `MFEN` is the initial position, `Demise` is the counts of
+    /// demise of Black and White at the end, and `Termination` is the reason of the result.
+    /// Times spent on the moves are written as `[%emt]` and evaluations as `[%eval]` in comments.
+    pub fn to_pgn(&self) -> Result<String, KifuError> {
+        let mut position = Position::from_str(&self.mfen)
+            .map_err(|e| KifuError::InvalidMfen(self.mfen.clone(), e))?;
+        let mut movetext = Vec::new();
+        let mut number = 1;
+        // Whether a move of White needs its number.
+        let mut white_number = true;
+        for (i, m) in self.moves.iter().enumerate() {
+            if !position.is_pseudo_legal(m.mv) || !position.is_legal(m.mv) {
+                let mv = move_to_mfen(m.mv, position.side);
+                return Err(KifuError::IllegalMove(i + 1, MoveError::Illegal(mv)));
+            }
+            let side = position.side;
+            let pgn = position.notation(m.mv);
+            if side == Side::Black {
+                movetext.push(format!("{}. {}", number, pgn));
+            } else if white_number {
+                movetext.push(format!("{}... {}", number, pgn));
+            } else {
+                movetext.push(pgn);
+            }
+            position.do_move(m.mv, None);
+            if position.side != side && side == Side::White {
+                number += 1;
+            }
+            white_number = side == Side::White || m.mv == MOVE_DEMISE;
+
+            let mut comment = Vec::new();
+            if let Some(time) = m.time {
+                comment.push(format!("[%emt {}]", clock_to_string(time)));
+            }
+            if let Some(eval) = m.eval {
+                let eval = if side == Side::White { eval } else { -eval };
+                comment.push(format!("[%eval {}]", eval_to_string(eval)));
+            }
+            if let Some(text) = &m.comment {
+                comment.push(single_line(text).replace('{', "(").replace('}', ")"));
+            }
+            if !comment.is_empty() {
+                movetext.push(format!("{{{}}}", comment.join(" ")));
+                white_number = true;
+            }
+        }
+        movetext.push(result_to_pgn(self.result).to_string());
+
+        let date = self
+            .date
+            .as_ref()
+            .map_or("????.??.??".to_string(), |d| d.replace('-', "."));
+        let mut tags = vec![
+            ("Event", "?".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", date),
+            ("Round", "?".to_string()),
+            ("White", self.white.clone().unwrap_or("?".to_string())),
+            ("Black", self.black.clone().unwrap_or("?".to_string())),
+            ("Result", result_to_pgn(self.result).to_string()),
+            ("MFEN", self.mfen.clone()),
+            (
+                "Demise",
+                format!("{} {}", position.demise[0], position.demise[1]),
+            ),
+        ];
+        match self.result {
+            GameResult::Ongoing => {}
+            GameResult::Win(_, reason) => tags.push(("Termination", reason.to_string())),
+            GameResult::Draw(reason) => tags.push(("Termination", reason.to_string())),
+        }
+
+        let mut pgn = String::new();
+        for (name, value) in tags {
+            pgn += &format!("[{} {}]\n", name, quote(&value));
+        }
+        pgn.push('\n');
+        // Lines are wrapped at 80 characters, except for long comments.
+        let mut line = String::new();
+        for element in movetext {
+            if !line.is_empty() && line.len() + 1 + element.len() > 80 {
+                pgn += &line;
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line += &element;
+        }
+        pgn += &line;
+        pgn.push('\n');
+        Ok(pgn)
+    }
+
+    /// Reads a game written in PGN, replaying the moves to check them.
+    ///
+    /// Unknown tags are ignored. The reason of the result is taken from the final position
+    /// if `Termination` is missing.
+    pub fn from_pgn(s: &str) -> Result<Self, KifuError> {
+        let mut kifu = Kifu::default();
+        let mut result = None;
+        let mut termination = None;
+        let mut demise = None;
+        let mut movetext = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let trimmed = line.trim();
+            if !movetext.is_empty() || !trimmed.starts_with('[') {
+                if !trimmed.is_empty() {
+                    movetext.push((i + 1, line));
+                }
+                continue;
+            }
+            let syntax = || KifuError::Syntax(i + 1, line.to_string());
+            let tag = trimmed
+                .strip_prefix('[')
+                .and_then(|t| t.strip_suffix(']'))
+                .ok_or_else(syntax)?;
+            let (name, value) = tag.split_once(' ').ok_or_else(syntax)?;
+            let value = unquote(value.trim()).ok_or_else(syntax)?;
+            let known = value != "?" && !value.is_empty();
+            match name {
+                "White" if known => kifu.white = Some(value),
+                "Black" if known => kifu.black = Some(value),
+                "Date" if !value.contains('?') => kifu.date = Some(value.replace('.', "-")),
+                "MFEN" => kifu.mfen = value,
+                "Result" => result = Some((i + 1, line.to_string(), value)),
+                "Termination" => termination = Some(value),
+                "Demise" => demise = Some((i + 1, line, value)),
+                _ => {}
+            }
+        }
+
+        let mut position = Position::from_str(&kifu.mfen)
+            .map_err(|e| KifuError::InvalidMfen(kifu.mfen.clone(), e))?;
+        for (i, token) in tokenize(&movetext)? {
+            let word = match token {
+                Token::Comment(text) => {
+                    // Comments before the first move are dropped.
+                    if let Some(m) = kifu.moves.last_mut() {
+                        let side = match m.mv {
+                            MOVE_DEMISE => position.side,
+                            _ => !position.side,
+                        };
+                        read_comment(m, side, &text)
+                            .ok_or_else(|| KifuError::Syntax(i, format!("{{{}}}", text)))?;
+                    }
+                    continue;
+                }
+                Token::Word(word) => word,
+            };
+            if matches!(word.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") {
+                if result.is_none() {
+                    result = Some((i, word.clone(), word));
+                }
+                continue;
+            }
+            // Move numbers such as `1.` and `1...` are skipped.
+            let digits = word
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(word.len());
+            let word = match word[digits..].strip_prefix('.') {
+                Some(rest) if digits > 0 => rest.trim_start_matches('.'),
+                _ => &word,
+            };
+            if word.is_empty() {
+                continue;
+            }
+            let ply = kifu.moves.len() + 1;
+            let mv = position
+                .read_notation(word)
+                .map_err(|e| KifuError::IllegalMove(ply, e))?;
+            position.do_move(mv, None);
+            kifu.moves.push(KifuMove::new(mv));
+        }
+
+        if let Some((i, line, value)) = demise {
+            if value != format!("{} {}", position.demise[0], position.demise[1]) {
+                return Err(KifuError::Syntax(i, line.to_string()));
+            }
+        }
+        if let Some((i, line, value)) = result {
+            let syntax = || KifuError::Syntax(i, line.clone());
+            let final_result = position.game_result(None);
+            kifu.result = match (value.as_str(), termination) {
+                ("*", _) => GameResult::Ongoing,
+                ("1/2-1/2", Some(reason)) => {
+                    GameResult::Draw(DrawReason::from_str(&reason).map_err(|_| syntax())?)
+                }
+                ("1-0" | "0-1", Some(reason)) => {
+                    let winner = if value == "1-0" {
+                        Side::White
+                    } else {
+                        Side::Black
+                    };
+                    GameResult::Win(winner, WinReason::from_str(&reason).map_err(|_| syntax())?)
+                }
+                (value, None) if result_to_pgn(final_result) == value => final_result,
+                _ => return Err(syntax()),
+            };
+        }
+        Ok(kifu)
+    }
+}
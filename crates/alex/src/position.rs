@@ -1,27 +1,36 @@
 use core::fmt;
-use std::{str::FromStr, usize};
+use std::str::FromStr;
 
 use num_traits::FromPrimitive;
 
 use crate::{
     bitboard::KG_BITBOARD,
     change_bit, foreach_bb,
+    movegen::{GenType, MoveList},
     types::{
-        count_hand, get_capture, get_from, get_move_type, get_pt, get_to, is_demise,
+        bit, count_hand, get_capture, get_from, get_move_type, get_pt, get_to, is_demise,
         make_move_drop, make_move_normal, make_move_return, make_move_shoot, make_move_supply,
-        read_file, read_rank, to_hand, Bitboard, Hand, Move, MoveType, Piece, PieceType, Side,
-        Square, MOVE_DEMISE, PIECE_TYPE_NB, RANK_NB, SIDE_NB, SQUARE_NB,
+        read_file, read_rank, to_hand, Bitboard, Hand, Key, Move, MoveError, MoveType, Piece,
+        PieceType, Side, Square, MOVE_DEMISE, PIECE_TYPE_NB, RANK_NB, SIDE_NB, SQUARE_NB,
     },
+    zobrist::{DEMISE_NB, HAND_COUNT_NB, HAND_PIECE_TYPES, ZOBRIST},
 };
 
 /// Count of occupation.
 pub const OCC_NB: usize = 64;
 
+/// MFEN of the initial position of a normal game.
+pub const STARTPOS: &str = "bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0";
+
 #[derive(PartialEq, Eq, Clone)]
 pub struct StateInfo {
+    /// Zobrist key of the position.
+    pub key: Key,
     pub checkers: Bitboard,
     pub blockers_king: Bitboard,
     pub blockers_prince: Bitboard,
+    /// Pieces of the side to move whose move can give a discovered check.
+    pub check_blockers: Bitboard,
     pub check_bb: [Bitboard; PIECE_TYPE_NB],
 }
 
@@ -46,27 +55,61 @@ impl StateInfo {
             change_bit!(blockers, (y - 1) * RANK_NB + x);
         }
         for pt in [PieceType::Archer1, PieceType::Archer2] {
-            for side in [Side::Black, Side::White] {
-                for sq2 in position.piece_list[!side as usize][pt as usize] {
-                    if sq2 == Square::NONE {
-                        break;
-                    }
-                    let line = KG_BITBOARD.between_bb[sq2 as usize][sq as usize];
-                    let occ = position.pieces() & line;
-                    if occ == 0 {
-                        continue;
-                    }
-                    // Tests whether there is just one piece between an archer and the crown.
-                    if occ & (occ - 1) == 0 {
-                        blockers |= occ;
-                    }
+            for sq2 in position.piece_list[!position.side as usize][pt as usize] {
+                if sq2 == Square::NONE {
+                    break;
+                }
+                let line = KG_BITBOARD.between_bb[sq2 as usize][sq as usize];
+                let occ = position.pieces() & line;
+                if occ == 0 {
+                    continue;
+                }
+                // Tests whether there is just one piece between an archer and the crown.
+                if occ & (occ - 1) == 0 {
+                    blockers |= occ;
                 }
             }
         }
         blockers
     }
 
-    pub fn new(position: &Position, checkers: Bitboard) -> Self {
+    /// Returns the pieces of the side to move which are the only piece between its heavy or
+    /// archer and the opponent's crown on `sq`.
+    fn calculate_check_blockers(position: &Position, sq: Square) -> Bitboard {
+        if sq == Square::NONE {
+            return 0;
+        }
+        let mut blockers = 0;
+        let x = sq as usize % 8;
+        let y = sq as usize / 8;
+        if position.side == Side::Black
+            && y >= 2
+            && position.grid[(y - 2) * RANK_NB + x] == Piece::BHeavy
+        {
+            change_bit!(blockers, (y - 1) * RANK_NB + x);
+        }
+        if position.side == Side::White
+            && y <= 5
+            && position.grid[(y + 2) * RANK_NB + x] == Piece::WHeavy
+        {
+            change_bit!(blockers, (y + 1) * RANK_NB + x);
+        }
+        for pt in [PieceType::Archer1, PieceType::Archer2] {
+            for sq2 in position.piece_list[position.side as usize][pt as usize] {
+                if sq2 == Square::NONE {
+                    break;
+                }
+                let occ = position.pieces() & KG_BITBOARD.between_bb[sq2 as usize][sq as usize];
+                if occ != 0 && occ & (occ - 1) == 0 {
+                    blockers |= occ;
+                }
+            }
+        }
+        blockers & position.pieces_side(position.side)
+    }
+
+    #[allow(clippy::needless_range_loop)]
+    pub fn new(position: &Position, checkers: Bitboard, key: Key) -> Self {
         let opp_crown = position.crown_sq(!position.side);
         let mut check_bb = [0; PIECE_TYPE_NB];
         for i in 1..PIECE_TYPE_NB {
@@ -75,17 +118,18 @@ impl StateInfo {
             check_bb[i] = KG_BITBOARD.check_bb[p as usize][opp_crown as usize];
             match pt {
                 PieceType::Heavy => {
-                    let x = i % 8;
-                    let y = i / 8;
+                    // Two steps forward over the empty square in front of the crown.
+                    let x = opp_crown as usize % 8;
+                    let y = opp_crown as usize / 8;
                     if position.side == Side::Black
                         && y >= 2
-                        && position.grid[(y - 2) * RANK_NB + x] == Piece::None
+                        && position.grid[(y - 1) * RANK_NB + x] == Piece::None
                     {
                         change_bit!(check_bb[i], (y - 2) * RANK_NB + x);
                     }
                     if position.side == Side::White
                         && y <= 5
-                        && position.grid[(y + 2) * RANK_NB + x] == Piece::None
+                        && position.grid[(y + 1) * RANK_NB + x] == Piece::None
                     {
                         change_bit!(check_bb[i], (y + 2) * RANK_NB + x);
                     }
@@ -101,14 +145,87 @@ impl StateInfo {
         let our_prince = position.piece_list[position.side as usize][PieceType::Prince as usize][0];
 
         StateInfo {
+            key,
             checkers,
             blockers_king: Self::calculate_blockers(position, our_king),
             blockers_prince: Self::calculate_blockers(position, our_prince),
+            check_blockers: Self::calculate_check_blockers(position, opp_crown),
             check_bb,
         }
     }
 }
 
+/// State of a repeated position.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RepetitionState {
+    /// The position has not appeared before.
+    None,
+    /// The position is repeated without perpetual check.
+    Draw,
+    /// The side to move has been checked perpetually by the opponent.
+    Win,
+    /// The side to move has been checking the opponent perpetually.
+    Lose,
+}
+
+/// Error of reading a position in MFEN.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MfenError {
+    /// The count of the fields is not 5.
+    FieldCount(usize),
+    InvalidPiece(char),
+    /// The character in the rank, counted from 1, is neither a piece nor a count of squares.
+    InvalidChar(char, usize),
+    /// The rank, counted from 1, does not have 8 squares.
+    InvalidRank(usize),
+    TooManyRanks,
+    /// There are more pieces of the type than the piece list holds.
+    TooManyPieces(char),
+    InvalidSide(String),
+    /// The piece cannot be in hand.
+    InvalidHandPiece(char),
+    /// The count of the piece in hand is written but less than 2.
+    InvalidHandCount(char, usize),
+    TooManyInHand(char),
+    InvalidDemise(Side, String),
+    NoCrown(Side),
+    /// The side not to move is in check.
+    OpponentInCheck,
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Black => "black",
+        Side::White => "white",
+    }
+}
+
+impl fmt::Display for MfenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MfenError::FieldCount(count) => write!(f, "mfen has {} fields, not 5", count),
+            MfenError::InvalidPiece(c) => write!(f, "invalid piece: {}", c),
+            MfenError::InvalidChar(c, rank) => write!(f, "invalid char in rank {}: {}", rank, c),
+            MfenError::InvalidRank(rank) => write!(f, "rank {} does not have 8 squares", rank),
+            MfenError::TooManyRanks => write!(f, "too many ranks"),
+            MfenError::TooManyPieces(c) => write!(f, "too many pieces: {}", c),
+            MfenError::InvalidSide(side) => write!(f, "invalid side to move: {}", side),
+            MfenError::InvalidHandPiece(c) => write!(f, "invalid piece in hand: {}", c),
+            MfenError::InvalidHandCount(c, count) => {
+                write!(f, "invalid count of {} in hand: {}", c, count)
+            }
+            MfenError::TooManyInHand(c) => write!(f, "too many pieces in hand: {}", c),
+            MfenError::InvalidDemise(side, demise) => {
+                write!(f, "invalid demise of {}: {}", side_name(*side), demise)
+            }
+            MfenError::NoCrown(side) => write!(f, "no crown of {}", side_name(*side)),
+            MfenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
+impl std::error::Error for MfenError {}
+
 /// Position.
 #[derive(PartialEq, Eq, Clone)]
 pub struct Position {
@@ -135,6 +252,12 @@ pub struct Position {
     pub states: Vec<StateInfo>,
 }
 
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Position {
     /// Create an empty board.
     pub fn new() -> Position {
@@ -261,13 +384,21 @@ impl Position {
         self.piece_list[side as usize][pt as usize][self.index[to as usize]] = to;
     }
 
-    pub fn do_move(&mut self, m: Move, checkers: Option<Bitboard>) {
+    /// Plays the move. `gives_check` is the result of `gives_check` if it is known.
+    pub fn do_move(&mut self, m: Move, gives_check: Option<bool>) {
+        let mut key = self.key();
         if is_demise(m) {
+            let demise = self.demise[self.side as usize];
+            key ^=
+                ZOBRIST.demise_key(self.side, demise) ^ ZOBRIST.demise_key(self.side, demise + 1);
             self.demise[self.side as usize] += 1;
             if m == MOVE_DEMISE {
+                // Demise alone does not pass the turn.
+                self.states.last_mut().unwrap().key = key;
                 return;
             }
         }
+        let hands = self.hands;
         let to = get_to(m);
         match get_move_type(m) {
             MoveType::Normal => {
@@ -275,15 +406,22 @@ impl Position {
                 let cap = get_capture(m);
 
                 if cap != PieceType::None {
+                    key ^= ZOBRIST.psq[self.grid[to as usize] as usize][to as usize];
                     self.remove_piece(to);
                     self.add_hand(self.side, cap);
                 }
+                let p = self.grid[from as usize];
+                key ^=
+                    ZOBRIST.psq[p as usize][from as usize] ^ ZOBRIST.psq[p as usize][to as usize];
                 self.move_piece(from, to);
             }
             MoveType::Return => {
                 let from = get_from(m);
-                let (pt, side) = self.grid[to as usize].split();
+                let p = self.grid[to as usize];
+                let (pt, side) = p.split();
 
+                key ^= ZOBRIST.psq[self.grid[from as usize] as usize][from as usize];
+                key ^= ZOBRIST.psq[p as usize][to as usize];
                 self.remove_piece(from);
                 self.remove_piece(to);
                 if pt == PieceType::Archer0 {
@@ -291,17 +429,21 @@ impl Position {
                 } else if pt == PieceType::Archer1 {
                     self.add_piece(PieceType::Archer2, side, to);
                 }
+                key ^= ZOBRIST.psq[self.grid[to as usize] as usize][to as usize];
             }
             MoveType::Shoot => {
                 let from = get_from(m);
-                let (pt, side) = self.grid[from as usize].split();
+                let p = self.grid[from as usize];
+                let (pt, side) = p.split();
                 let cap = get_capture(m);
 
                 if cap != PieceType::None {
+                    key ^= ZOBRIST.psq[self.grid[to as usize] as usize][to as usize];
                     self.remove_piece(to);
                     self.add_hand(side, cap);
                 }
 
+                key ^= ZOBRIST.psq[p as usize][from as usize];
                 self.remove_piece(from);
                 if pt == PieceType::Archer1 {
                     self.add_piece(PieceType::Archer0, side, from);
@@ -309,40 +451,55 @@ impl Position {
                     self.add_piece(PieceType::Archer1, side, from);
                 }
                 self.add_piece(PieceType::Arrow, side, to);
+                key ^= ZOBRIST.psq[self.grid[from as usize] as usize][from as usize];
+                key ^= ZOBRIST.psq[self.grid[to as usize] as usize][to as usize];
             }
             MoveType::Drop => {
                 let pt = get_pt(m);
                 self.remove_hand(self.side, pt);
                 self.add_piece(pt, self.side, to);
+                key ^= ZOBRIST.psq[self.grid[to as usize] as usize][to as usize];
             }
             MoveType::Supply => {
                 self.remove_hand(self.side, PieceType::Arrow);
-                let pt = self.grid[to as usize].pt();
+                let p = self.grid[to as usize];
+                let pt = p.pt();
+                key ^= ZOBRIST.psq[p as usize][to as usize];
                 self.remove_piece(to);
                 if pt == PieceType::Archer0 {
                     self.add_piece(PieceType::Archer1, self.side, to);
                 } else if pt == PieceType::Archer1 {
                     self.add_piece(PieceType::Archer2, self.side, to);
                 }
+                key ^= ZOBRIST.psq[self.grid[to as usize] as usize][to as usize];
             }
         }
+        if hands[self.side as usize] != self.hands[self.side as usize] {
+            key ^= ZOBRIST.hand_key(self.side, hands[self.side as usize]);
+            key ^= ZOBRIST.hand_key(self.side, self.hands[self.side as usize]);
+        }
 
         self.side = !self.side;
+        key ^= ZOBRIST.side;
 
-        if let Some(checkers) = checkers {
-            self.states.push(StateInfo::new(self, checkers));
+        let checkers = if gives_check == Some(false) {
+            0
         } else {
-            self.states
-                .push(StateInfo::new(self, self.calculate_checkers()));
-        }
+            self.calculate_checkers()
+        };
+        self.states.push(StateInfo::new(self, checkers, key));
     }
 
     pub fn undo_move(&mut self, m: Move) {
+        if m == MOVE_DEMISE {
+            let demise = self.demise[self.side as usize];
+            self.states.last_mut().unwrap().key ^=
+                ZOBRIST.demise_key(self.side, demise) ^ ZOBRIST.demise_key(self.side, demise - 1);
+            self.demise[self.side as usize] -= 1;
+            return;
+        }
         if is_demise(m) {
             self.demise[!self.side as usize] -= 1;
-            if m == MOVE_DEMISE {
-                return;
-            }
         }
         // Change side in advance.
         self.side = !self.side;
@@ -411,8 +568,75 @@ impl Position {
         self.states.pop();
     }
 
-    /// Make a move from mfen.
-    pub fn read_move(&self, mfen: String) -> Result<Move, String> {
+    /// Passes the turn. The side to move must not be in check.
+    pub fn do_null_move(&mut self) {
+        debug_assert!(self.checkers() == 0);
+        let key = self.key() ^ ZOBRIST.side;
+        self.side = !self.side;
+        self.states.push(StateInfo::new(self, 0, key));
+    }
+
+    pub fn undo_null_move(&mut self) {
+        self.side = !self.side;
+        self.states.pop();
+    }
+
+    /// Reads a move in MFEN, which must be legal, telling why the move is rejected.
+    pub fn read_move(&self, mfen: String) -> Result<Move, MoveError> {
+        let mv = self.parse_move(&mfen)?;
+        if self.is_legal_move(mv) {
+            return Ok(mv);
+        }
+        if mv == MOVE_DEMISE {
+            return Err(MoveError::Illegal(mfen));
+        }
+
+        let side = self.side;
+        let has_piece = match get_move_type(mv) {
+            MoveType::Normal => {
+                let (pt, piece_side) = self.grid[get_from(mv) as usize].split();
+                pt != PieceType::None && piece_side == side
+            }
+            MoveType::Return => {
+                self.grid[get_from(mv) as usize] == PieceType::Arrow.into_piece(side)
+            }
+            MoveType::Shoot => {
+                let piece = self.grid[get_from(mv) as usize];
+                piece == PieceType::Archer1.into_piece(side)
+                    || piece == PieceType::Archer2.into_piece(side)
+            }
+            MoveType::Drop => match get_pt(mv) {
+                pt @ (PieceType::Light
+                | PieceType::Heavy
+                | PieceType::General
+                | PieceType::Knight
+                | PieceType::Arrow
+                | PieceType::Archer0) => self.count_hand(side, pt) > 0,
+                // Other pieces are never in hand.
+                _ => false,
+            },
+            MoveType::Supply => self.count_hand(side, PieceType::Arrow) > 0,
+        };
+        if !has_piece {
+            return Err(MoveError::NoSuchPiece(mfen));
+        }
+
+        // A move generated without regard to check leaves the crown attacked.
+        let mut list = MoveList::new();
+        if is_demise(mv) {
+            list.generate(self, GenType::Evasion);
+        } else {
+            list.generate(self, GenType::All);
+        }
+        if list.slice_mut(0).iter().any(|m| m.mv == mv) {
+            Err(MoveError::CrownAttacked(mfen))
+        } else {
+            Err(MoveError::Illegal(mfen))
+        }
+    }
+
+    /// Builds a move from MFEN, inferring its type from the board.
+    fn parse_move(&self, mfen: &str) -> Result<Move, MoveError> {
         if mfen == "D" {
             return Ok(MOVE_DEMISE);
         }
@@ -422,20 +646,20 @@ impl Position {
             len -= 1;
             demise = MOVE_DEMISE;
         }
-        let mfen = mfen.as_bytes();
+        let bytes = mfen.as_bytes();
         if len == 4 || len == 5 {
-            let x1 = read_file(mfen[0])?;
-            let y1 = read_rank(mfen[1])?;
+            let x1 = read_file(bytes[0])?;
+            let y1 = read_rank(bytes[1])?;
             let from = Square::from_usize(y1 * RANK_NB + x1).unwrap();
-            let x2 = read_file(mfen[2])?;
-            let y2 = read_rank(mfen[3])?;
+            let x2 = read_file(bytes[2])?;
+            let y2 = read_rank(bytes[3])?;
             let to = Square::from_usize(y2 * RANK_NB + x2).unwrap();
             let cap = self.grid[to as usize];
             if len == 5 {
-                if mfen[4] == b'S' {
+                if bytes[4] == b'S' {
                     Ok(make_move_shoot(cap.pt(), from, to) | demise)
                 } else {
-                    Err("Invalid end character.".to_string())
+                    Err(MoveError::InvalidSuffix(bytes[4] as char))
                 }
             } else {
                 if cap.pt() != PieceType::None && cap.side() == self.side {
@@ -445,10 +669,13 @@ impl Position {
                 }
             }
         } else if len == 3 {
-            let x = read_file(mfen[0])?;
-            let y = read_rank(mfen[1])?;
+            let x = read_file(bytes[0])?;
+            let y = read_rank(bytes[1])?;
             let to = Square::from_usize(y * RANK_NB + x).unwrap();
-            let pt = PieceType::from_char(mfen[2]);
+            let pt = PieceType::from_char(bytes[2]);
+            if pt == PieceType::None {
+                return Err(MoveError::InvalidPiece(bytes[2] as char));
+            }
             let to_pt = self.grid[to as usize].pt();
             if to_pt == PieceType::Archer0 || to_pt == PieceType::Archer1 {
                 Ok(make_move_supply(to) | demise)
@@ -456,18 +683,97 @@ impl Position {
                 Ok(make_move_drop(pt, to) | demise)
             }
         } else {
-            Err("Invalid length.".to_string())
+            Err(MoveError::InvalidLength(mfen.to_string()))
         }
     }
 
     pub fn crown_sq(&self, side: Side) -> Square {
-        if self.demise[side as usize] % 2 == 0 {
+        if self.demise[side as usize].is_multiple_of(2) {
             self.piece_list[side as usize][PieceType::King as usize][0]
         } else {
             self.piece_list[side as usize][PieceType::Prince as usize][0]
         }
     }
 
+    pub fn key(&self) -> Key {
+        self.states.last().unwrap().key
+    }
+
+    /// Computes the Zobrist key from scratch.
+    pub fn compute_key(&self) -> Key {
+        let mut key = 0;
+        for i in 0..SQUARE_NB {
+            if self.grid[i] != Piece::None {
+                key ^= ZOBRIST.psq[self.grid[i] as usize][i];
+            }
+        }
+        for side in [Side::Black, Side::White] {
+            key ^= ZOBRIST.hand_key(side, self.hands[side as usize]);
+            key ^= ZOBRIST.demise_key(side, self.demise[side as usize]);
+        }
+        if self.side == Side::White {
+            key ^= ZOBRIST.side;
+        }
+        key
+    }
+
+    /// Returns who gave perpetual check during the last `plies` plies.
+    fn perpetual_check(&self, plies: usize) -> RepetitionState {
+        let n = self.states.len() - 1;
+        // States where the side to move was checked in the cycle.
+        let checked = |start: usize| {
+            (start..plies)
+                .step_by(2)
+                .all(|j| self.states[n - j].checkers != 0)
+        };
+        match (checked(0), checked(1)) {
+            (true, false) => RepetitionState::Win,
+            (false, true) => RepetitionState::Lose,
+            _ => RepetitionState::Draw,
+        }
+    }
+
+    /// Returns whether the current position appeared before and who gave perpetual check.
+    pub fn repetition_state(&self) -> RepetitionState {
+        let n = self.states.len() - 1;
+        let key = self.states[n].key;
+        let mut i = 2;
+        while i <= n {
+            if self.states[n - i].key == key {
+                return self.perpetual_check(i);
+            }
+            i += 2;
+        }
+        RepetitionState::None
+    }
+
+    /// Returns how many times the current position has appeared, and the state of the
+    /// repetition since its first appearance.
+    pub fn repetition_count(&self) -> (usize, RepetitionState) {
+        let n = self.states.len() - 1;
+        let key = self.states[n].key;
+        let mut count = 1;
+        let mut first = 0;
+        let mut i = 2;
+        while i <= n {
+            if self.states[n - i].key == key {
+                count += 1;
+                first = i;
+            }
+            i += 2;
+        }
+        if count == 1 {
+            (1, RepetitionState::None)
+        } else {
+            (count, self.perpetual_check(first))
+        }
+    }
+
+    /// Returns the count of plies played from the initial position.
+    pub fn game_ply(&self) -> usize {
+        self.states.len() - 1
+    }
+
     pub fn checkers(&self) -> Bitboard {
         self.states.last().unwrap().checkers
     }
@@ -480,6 +786,51 @@ impl Position {
         self.states.last().unwrap().blockers_prince
     }
 
+    pub fn check_blockers(&self) -> Bitboard {
+        self.states.last().unwrap().check_blockers
+    }
+
+    /// Returns the squares from which a piece of the side to move checks the opponent.
+    pub fn check_bb(&self, pt: PieceType) -> Bitboard {
+        self.states.last().unwrap().check_bb[pt as usize]
+    }
+
+    /// Returns whether the move checks the opponent.
+    pub fn gives_check(&self, mv: Move) -> bool {
+        if mv == MOVE_DEMISE {
+            return false;
+        }
+        let crown_sq = self.crown_sq(!self.side) as usize;
+        let to = get_to(mv);
+        match get_move_type(mv) {
+            MoveType::Normal => {
+                let from = get_from(mv);
+                let pt = self.grid[from as usize].pt();
+                if bit(self.check_bb(pt), to as usize) == 1 {
+                    return true;
+                }
+                // Discovered check by leaving the line to the crown.
+                bit(self.check_blockers(), from as usize) == 1
+                    && bit(KG_BITBOARD.line_bb[from as usize][crown_sq], to as usize) == 0
+            }
+            MoveType::Return => {
+                // The archer gets an arrow, and the returned arrow may have blocked a line.
+                let from = get_from(mv) as usize;
+                let occupied = self.pieces() ^ (1 << from);
+                bit(KG_BITBOARD.arrow_attacks(occupied, to), crown_sq) == 1
+                    || bit(self.check_blockers(), from) == 1
+            }
+            // The archer loses an arrow, and the arrow only blocks lines.
+            MoveType::Shoot => false,
+            MoveType::Drop => bit(self.check_bb(get_pt(mv)), to as usize) == 1,
+            MoveType::Supply => {
+                // An archer with an arrow already checks if it can.
+                self.grid[to as usize].pt() == PieceType::Archer0
+                    && bit(self.check_bb(PieceType::Archer1), to as usize) == 1
+            }
+        }
+    }
+
     pub fn aligned(&self, sq1: Square, sq2: Square, sq3: Square) -> bool {
         KG_BITBOARD.line_bb[sq1 as usize][sq2 as usize] & (1 << sq3 as usize) != 0
     }
@@ -538,7 +889,7 @@ impl Position {
 }
 
 impl Piece {
-    fn from_char(c: char) -> Result<Self, String> {
+    fn from_char(c: char) -> Result<Self, MfenError> {
         let p = match c {
             'L' => Piece::BLight,
             'H' => Piece::BHeavy,
@@ -560,7 +911,7 @@ impl Piece {
             'a' => Piece::WArcher0,
             'b' => Piece::WArcher1,
             'c' => Piece::WArcher2,
-            _ => return Err(format!("invalid char: {}.", c)),
+            _ => return Err(MfenError::InvalidPiece(c)),
         };
         Ok(p)
     }
@@ -635,7 +986,7 @@ impl fmt::Display for Position {
 }
 
 impl FromStr for Position {
-    type Err = String;
+    type Err = MfenError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut position = Position::new();
@@ -643,17 +994,17 @@ impl FromStr for Position {
         let mut iy = RANK_NB - 1;
         let s: Vec<&str> = s.split(" ").collect();
         if s.len() != 5 {
-            return Err("invalid mfen.".to_string());
+            return Err(MfenError::FieldCount(s.len()));
         }
         for c in s[0].chars() {
             let piece = match c {
                 '/' => {
                     if ix != RANK_NB {
-                        return Err("invalid row.".to_string());
+                        return Err(MfenError::InvalidRank(iy + 1));
                     }
                     ix = 0;
                     if iy == 0 {
-                        return Err("too many rows.".to_string());
+                        return Err(MfenError::TooManyRanks);
                     }
                     iy -= 1;
                     continue;
@@ -662,24 +1013,30 @@ impl FromStr for Position {
                     if let Ok(p) = Piece::from_char(c) {
                         p
                     } else {
-                        let i = c as i32 - 48;
-                        if i < 0 || ix + i as usize > RANK_NB {
-                            return Err(format!("invalid char: {}.", c));
+                        let i = c.to_digit(10).unwrap_or(0) as usize;
+                        if i == 0 || ix + i > RANK_NB {
+                            return Err(MfenError::InvalidChar(c, iy + 1));
                         }
-                        ix += i as usize;
+                        ix += i;
                         continue;
                     }
                 }
             };
+            if ix >= RANK_NB {
+                return Err(MfenError::InvalidRank(iy + 1));
+            }
             let i = iy * RANK_NB + ix;
             let (pt, side) = piece.split();
-            if pt != PieceType::None {
-                position.add_piece(pt, side, Square::from_usize(i).unwrap());
+            // The piece list holds at most 8 pieces of each type, and there is one crown of each.
+            let count = position.piece_count[side as usize][pt as usize];
+            if count >= 8 || count >= 1 && (pt == PieceType::King || pt == PieceType::Prince) {
+                return Err(MfenError::TooManyPieces(c));
             }
+            position.add_piece(pt, side, Square::from_usize(i).unwrap());
             ix += 1;
         }
         if ix != RANK_NB || iy != 0 {
-            return Err("invalid number.".to_string());
+            return Err(MfenError::InvalidRank(iy + 1));
         }
 
         if s[1] == "b" {
@@ -687,7 +1044,7 @@ impl FromStr for Position {
         } else if s[1] == "w" {
             position.side = Side::White;
         } else {
-            return Err("invalid turn.".to_string());
+            return Err(MfenError::InvalidSide(s[1].to_string()));
         }
 
         if s[2] != "-" {
@@ -695,39 +1052,63 @@ impl FromStr for Position {
             let mut i = 0;
             while i < hand.len() {
                 let p = Piece::from_char(hand[i])?;
+                if !HAND_PIECE_TYPES.contains(&p.pt()) {
+                    return Err(MfenError::InvalidHandPiece(hand[i]));
+                }
                 i += 1;
-                if i >= hand.len() || Piece::from_char(hand[i]).is_ok() {
-                    position.add_hand(p.side(), p.pt());
-                    break;
+                let start = i;
+                while i < hand.len() && hand[i].is_ascii_digit() && i - start < 2 {
+                    i += 1;
                 }
-                let count = hand[i] as i32 - 48;
-                if count <= 1 {
-                    return Err(format!("invalid char: {}.", hand[i]));
+                let count = if i == start {
+                    1
+                } else {
+                    let count: usize = hand[start..i].iter().collect::<String>().parse().unwrap();
+                    if count <= 1 {
+                        return Err(MfenError::InvalidHandCount(hand[start - 1], count));
+                    }
+                    count
+                };
+                if position.count_hand(p.side(), p.pt()) as usize + count >= HAND_COUNT_NB {
+                    return Err(MfenError::TooManyInHand(hand[start - 1]));
                 }
-                i += 1;
                 for _ in 0..count {
                     position.add_hand(p.side(), p.pt());
                 }
             }
         }
 
-        if let Ok(count) = s[3].parse() {
-            position.demise[0] = count;
-        } else {
-            return Err(format!("invalid demise: {}", s[3]));
+        match s[3].parse() {
+            Ok(count) if count < DEMISE_NB => position.demise[0] = count,
+            _ => return Err(MfenError::InvalidDemise(Side::Black, s[3].to_string())),
         }
 
-        if let Ok(count) = s[4].parse() {
-            position.demise[1] = count;
-        } else {
-            return Err(format!("invalid demise: {}", s[4]));
+        match s[4].parse() {
+            Ok(count) if count < DEMISE_NB => position.demise[1] = count,
+            _ => return Err(MfenError::InvalidDemise(Side::White, s[4].to_string())),
+        }
+
+        for side in [Side::Black, Side::White] {
+            if position.crown_sq(side) == Square::NONE {
+                return Err(MfenError::NoCrown(side));
+            }
         }
 
         position.effects = position.calculate_effects();
 
-        position
-            .states
-            .push(StateInfo::new(&position, position.calculate_checkers()));
+        // The side to move cannot leave its opponent in check.
+        position.side = !position.side;
+        let checked = position.calculate_checkers() != 0;
+        position.side = !position.side;
+        if checked {
+            return Err(MfenError::OpponentInCheck);
+        }
+
+        position.states.push(StateInfo::new(
+            &position,
+            position.calculate_checkers(),
+            position.compute_key(),
+        ));
 
         Ok(position)
     }
@@ -1,84 +1,398 @@
-use std::mem::MaybeUninit;
-
-use chrono::{DateTime, Local, TimeDelta};
+use std::{
+    fmt,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+};
 
 use super::{
-    eval::eval,
+    eval::{evaluator, EvalParams, Evaluator},
     movegen::{GenType, MoveList},
-    movepick::MovePicker,
-    position::Position,
-    types::{Move, Value, MAX_PLY, VALUE_INF, VALUE_WIN},
+    movepick::{is_quiet, History, MovePicker},
+    position::{Position, RepetitionState},
+    timeman::{SystemClock, TimeManager},
+    tt::{value_from_tt, value_to_tt, Bound, TranspositionTable},
+    types::{
+        lose_in, move_to_mfen, win_in, Move, PieceType, Value, MAX_PLY, MOVE_NONE, SIDE_NB,
+        VALUE_DRAW, VALUE_INF, VALUE_WIN, VALUE_WIN_IN_MAX_PLY,
+    },
 };
 
-struct TimeKeeper {
-    start: DateTime<Local>,
-    duration: TimeDelta,
+/// Limits of a search. Times are in milliseconds.
+#[derive(Clone, Default, Debug)]
+pub struct SearchLimits {
+    /// Remaining time of the sides.
+    pub time: [Option<u64>; SIDE_NB],
+    /// Increment of the sides.
+    pub inc: [u64; SIDE_NB],
+    pub byoyomi: u64,
+    /// Exact time to search.
+    pub movetime: Option<u64>,
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    /// Searches for a mate in the number of moves.
+    pub mate: Option<usize>,
+    /// Searches until stopped.
+    pub infinite: bool,
+}
+
+impl SearchLimits {
+    /// Limits to search for `ms` milliseconds.
+    pub fn movetime(ms: u64) -> Self {
+        SearchLimits {
+            movetime: Some(ms),
+            ..Default::default()
+        }
+    }
+}
+
+/// Flags to control a running search from another thread.
+#[derive(Default)]
+pub struct Signals {
+    /// Stops the search as soon as possible.
+    pub stop: AtomicBool,
+    /// The time limit is ignored while pondering.
+    pub ponder: AtomicBool,
+}
+
+/// How repeated positions are scored by the search.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum RepetitionRule {
+    /// Repetition is a draw, but the side giving perpetual check loses.
+    #[default]
+    Sennichite,
+    /// Repetition is always a draw.
+    Draw,
+}
+
+impl RepetitionRule {
+    /// Names of the rules, which are the values of the option `RepetitionRule`.
+    pub const NAMES: [&'static str; 2] = ["Sennichite", "Draw"];
+
+    /// Returns the rule of the name in `NAMES`, ignoring the case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sennichite" => Some(RepetitionRule::Sennichite),
+            "draw" => Some(RepetitionRule::Draw),
+            _ => None,
+        }
+    }
+
+    fn value(&self, state: RepetitionState, ply: usize) -> Option<Value> {
+        match (self, state) {
+            (_, RepetitionState::None) => None,
+            (RepetitionRule::Sennichite, RepetitionState::Win) => Some(win_in(ply)),
+            (RepetitionRule::Sennichite, RepetitionState::Lose) => Some(lose_in(ply)),
+            _ => Some(VALUE_DRAW),
+        }
+    }
+}
+
+/// Maximum skill level, which plays at full strength.
+pub const MAX_SKILL_LEVEL: usize = 20;
+
+/// Parameters of the selective search.
+/// Each technique can be disabled to measure its effect on the strength.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SearchParams {
+    /// Prunes a node if it fails high even after passing the turn.
+    pub null_move: bool,
+    /// Minimum depth to try a null move.
+    pub null_move_depth: usize,
+    /// Reduction of the null move search, increased by one for every 4 plies of depth.
+    pub null_move_reduction: usize,
+    /// Searches late quiet moves with a reduced depth first.
+    pub lmr: bool,
+    /// Minimum depth to reduce moves.
+    pub lmr_depth: usize,
+    /// Count of moves searched without the reduction.
+    pub lmr_moves: usize,
+    /// The reduction is ln(depth) * ln(move count) * `lmr_scale` / 1024.
+    pub lmr_scale: i32,
+    /// History score which decreases the reduction by one ply.
+    pub lmr_history: i32,
+    /// Prunes a node if the static evaluation exceeds beta by a margin.
+    pub reverse_futility: bool,
+    /// Maximum depth of the reverse futility pruning.
+    pub reverse_futility_depth: usize,
+    /// Margin of the reverse futility pruning per ply.
+    pub reverse_futility_margin: Value,
+    /// Skips quiet moves if the static evaluation is below alpha by a margin.
+    pub futility: bool,
+    /// Maximum depth of the futility pruning.
+    pub futility_depth: usize,
+    /// Margin of the futility pruning per ply.
+    pub futility_margin: Value,
+    /// Searches moves giving check one ply deeper.
+    pub check_extension: bool,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            null_move: true,
+            null_move_depth: 3,
+            null_move_reduction: 2,
+            lmr: true,
+            lmr_depth: 3,
+            lmr_moves: 3,
+            lmr_scale: 512,
+            lmr_history: 8192,
+            reverse_futility: true,
+            reverse_futility_depth: 6,
+            reverse_futility_margin: 120,
+            futility: true,
+            futility_depth: 3,
+            futility_margin: 200,
+            check_extension: true,
+        }
+    }
+}
+
+impl SearchParams {
+    /// Parameters with all the selectivity disabled.
+    pub fn none() -> Self {
+        SearchParams {
+            null_move: false,
+            lmr: false,
+            reverse_futility: false,
+            futility: false,
+            check_extension: false,
+            ..Default::default()
+        }
+    }
+}
+
+/// Options of a search.
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    pub rule: RepetitionRule,
+    /// Count of search threads.
+    pub threads: usize,
+    /// Count of principal variations to report.
+    pub multi_pv: usize,
+    /// Time in milliseconds kept for the communication with the GUI.
+    pub move_overhead: u64,
+    /// Lower levels limit the depth of the search.
+    pub skill_level: usize,
+    pub params: SearchParams,
+    pub eval_params: EvalParams,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            rule: RepetitionRule::default(),
+            threads: 1,
+            multi_pv: 1,
+            move_overhead: 100,
+            skill_level: MAX_SKILL_LEVEL,
+            params: SearchParams::default(),
+            eval_params: EvalParams::default(),
+        }
+    }
 }
 
-impl TimeKeeper {
-    fn new(duration: f64) -> Self {
-        let start = chrono::Local::now();
-        let secs = duration.floor() as i64;
-        let nanos = ((duration - secs as f64) * 1e9) as u32;
-        let duration = TimeDelta::new(secs, nanos).unwrap();
+impl SearchOptions {
+    /// Returns the maximum depth allowed by the skill level.
+    fn max_depth(&self) -> usize {
+        if self.skill_level >= MAX_SKILL_LEVEL {
+            MAX_MOVE
+        } else {
+            self.skill_level / 4 + 1
+        }
+    }
+}
+
+/// Score of a position from the side to move.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Score {
+    /// Evaluation in centipawns.
+    Cp(Value),
+    /// Mate in plies. Negative if the side to move is mated.
+    Mate(i32),
+}
+
+/// Returns the plies to the mate if the side to move mates.
+fn value_to_mate(value: Value) -> Option<usize> {
+    if value >= VALUE_WIN_IN_MAX_PLY {
+        Some((VALUE_WIN - value) as usize)
+    } else {
+        None
+    }
+}
 
-        TimeKeeper { start, duration }
+impl From<Value> for Score {
+    fn from(value: Value) -> Self {
+        if value >= VALUE_WIN_IN_MAX_PLY {
+            Score::Mate((VALUE_WIN - value) as i32)
+        } else if value <= -VALUE_WIN_IN_MAX_PLY {
+            Score::Mate(-((VALUE_WIN + value) as i32))
+        } else {
+            Score::Cp(value)
+        }
     }
+}
 
-    fn passed(&self) -> bool {
-        let now = chrono::Local::now();
-        now - self.start > self.duration
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Score::Cp(value) => write!(f, "cp {}", value),
+            Score::Mate(ply) => write!(f, "mate {}", ply),
+        }
     }
 }
 
+/// Progress of a search, reported after each completed iteration.
+#[derive(Clone, Debug)]
+pub struct IterationInfo {
+    pub depth: usize,
+    /// Rank of the line from 1 in the MultiPV mode.
+    pub multipv: usize,
+    /// Maximum ply reached, including the quiescence search.
+    pub seldepth: usize,
+    pub score: Score,
+    pub nodes: u64,
+    /// Nodes per second.
+    pub nps: u64,
+    /// Elapsed time in milliseconds.
+    pub time: u64,
+    /// Permille of the transposition table in use.
+    pub hashfull: usize,
+    /// Principal variation in MFEN notation.
+    pub pv: Vec<String>,
+}
+
 pub struct SearchInfo {
     pub mv: Move,
     pub depth: usize,
     pub value: Value,
+    /// Count of searched nodes.
+    pub nodes: u64,
+    /// Root moves with the values and the principal variations.
+    /// The first `SearchOptions::multi_pv` of them are the best lines with exact values.
     pub root_moves: Vec<(Move, Value, Vec<Move>)>,
 }
 
-pub fn search(position: &mut Position, time: f64) -> Option<SearchInfo> {
-    let keeper = TimeKeeper::new(time);
+/// Searches the position with `options.threads` threads.
+/// Helper threads search clones of the position and share only the transposition table,
+/// while the main thread reports the progress and decides the best move.
+pub fn search(
+    position: &mut Position,
+    limits: &SearchLimits,
+    tt: &TranspositionTable,
+    options: &SearchOptions,
+    signals: &Signals,
+    on_iteration: &mut dyn FnMut(&IterationInfo),
+) -> Option<SearchInfo> {
+    tt.new_search();
+    let threads = options.threads.max(1);
+    let shared = Shared {
+        signals,
+        tt,
+        abort: AtomicBool::new(false),
+        nodes: (0..threads).map(|_| AtomicU64::new(0)).collect(),
+        max_nodes: limits.nodes,
+    };
     let mut moves = MoveList::new();
     moves.generate(position, GenType::Legal);
-    let mut depth = 1;
-    let mut result = Vec::new();
-    loop {
-        let res = search_root(&moves, position, -VALUE_INF, VALUE_INF, depth, &keeper);
-        if keeper.passed() {
-            break;
-        } else {
-            result = res;
+    // A mate in n moves is found within 2n - 1 plies.
+    let mate_ply = limits.mate.map(|n| (2 * n).saturating_sub(1));
+    let max_depth = limits
+        .depth
+        .or(mate_ply)
+        .unwrap_or(MAX_MOVE)
+        .min(options.max_depth())
+        .clamp(1, MAX_MOVE);
+    let root_moves: Vec<Move> = (0..moves.size).map(|i| moves.at(i).mv).collect();
+
+    let (mut result, searched, depth) = thread::scope(|scope| {
+        for id in 1..threads {
+            let mut position = position.clone();
+            let root_moves = root_moves.clone();
+            let shared = &shared;
+            scope.spawn(move || {
+                let mut searcher = Searcher::new(id, shared, limits, options, &position);
+                searcher.iterate(&mut position, root_moves, max_depth, mate_ply, &mut |_| {});
+            });
         }
-        depth += 1;
+        let mut searcher = Searcher::new(0, &shared, limits, options, position);
+        let result = searcher.iterate(position, root_moves, max_depth, mate_ply, on_iteration);
+        shared.abort.store(true, Ordering::Relaxed);
+        result
+    });
+
+    if result.is_empty() && moves.size > 0 {
+        result.push((moves.at(0).mv, VALUE_DRAW, Line::new()));
     }
+    let searched = searched.min(result.len());
     // max_by_key returns the last max value, so we need to reverse the iterator.
-    if let Some((mv, value, _)) = result.clone().into_iter().rev().max_by_key(|v| v.1) {
-        let mut root_moves = Vec::new();
-        for (mv, value, line) in result {
-            let mut moves = Vec::new();
-            for i in 0..line.size {
-                moves.push(unsafe { *line.moves.get_unchecked(i).as_ptr() });
-            }
-            root_moves.push((mv, value, moves));
-        }
+    if let Some((mv, value, _)) = result[..searched].iter().rev().max_by_key(|v| v.1) {
         Some(SearchInfo {
-            mv,
+            mv: *mv,
             depth,
-            value,
-            root_moves,
+            value: *value,
+            nodes: shared.nodes(),
+            root_moves: result
+                .iter()
+                .map(|(mv, value, line)| (*mv, *value, line.moves().to_vec()))
+                .collect(),
         })
     } else {
         None
     }
 }
 
+/// Returns the move with the highest value, preferring the earlier one.
+fn best_move(result: &[(Move, Value, Line)]) -> Option<Move> {
+    result.iter().rev().max_by_key(|v| v.1).map(|v| v.0)
+}
+
+/// Returns the best move of an unfinished iteration if it can replace `prev`, the best move
+/// of the last completed one, where `alpha` is the lower end of the window of the iteration.
+/// `prev` must have been searched again, and the best value must be exact or fail high since
+/// the values of the moves failing low are only upper bounds.
+pub(crate) fn partial_best_move(
+    res: &[(Move, Value)],
+    prev: Option<Move>,
+    alpha: Value,
+) -> Option<Move> {
+    if !res.iter().any(|r| Some(r.0) == prev) {
+        return None;
+    }
+    res.iter()
+        .rev()
+        .max_by_key(|r| r.1)
+        .filter(|r| r.1 > alpha)
+        .map(|r| r.0)
+}
+
+/// Returns true if passing the turn may be the best move of the side to move,
+/// which is likely when it has nothing in hand and only crowns and lights on the board.
+fn zugzwang_prone(position: &Position) -> bool {
+    let side = position.side;
+    let crowns_and_lights = position.pieces_pt_side(PieceType::King, side)
+        | position.pieces_pt_side(PieceType::Prince, side)
+        | position.pieces_pt_side(PieceType::Light, side);
+    position.hands[side as usize] == 0 && position.pieces_side(side) == crowns_and_lights
+}
+
 const MAX_MOVE: usize = 64;
 
+/// Initial half width of the aspiration window.
+const ASPIRATION_DELTA: Value = 25;
+/// Minimum depth to use the aspiration window.
+const ASPIRATION_DEPTH: usize = 4;
+/// Maximum count of quiet moves whose history is lowered on a beta cutoff.
+const QUIETS_NB: usize = 64;
+
 #[derive(Clone)]
 struct Line {
-    moves: [MaybeUninit<Move>; MAX_MOVE],
+    /// Every ply of the search fits in the line, including the extended ones.
+    moves: [MaybeUninit<Move>; MAX_PLY],
     size: usize,
 }
 
@@ -89,163 +403,638 @@ impl Line {
             size: 0,
         }
     }
+
+    fn moves(&self) -> &[Move] {
+        unsafe { std::slice::from_raw_parts(self.moves.as_ptr() as *const Move, self.size) }
+    }
 }
 
-fn search_root(
-    moves: &MoveList,
-    position: &mut Position,
-    alpha: Value,
-    beta: Value,
-    depth: usize,
-    keeper: &TimeKeeper,
-) -> Vec<(Move, Value, Line)> {
-    let mut vec = Vec::new();
+/// State shared by the threads of a search.
+struct Shared<'a> {
+    signals: &'a Signals,
+    tt: &'a TranspositionTable,
+    /// Stops the helper threads when the main thread finishes.
+    abort: AtomicBool,
+    /// Count of searched nodes of each thread.
+    nodes: Vec<AtomicU64>,
+    max_nodes: Option<u64>,
+}
+
+impl Shared<'_> {
+    /// Returns the count of nodes searched by all the threads.
+    fn nodes(&self) -> u64 {
+        self.nodes.iter().map(|n| n.load(Ordering::Relaxed)).sum()
+    }
+}
 
-    let mut alpha = alpha;
+/// State of a thread shared by the nodes of a search.
+struct Searcher<'a> {
+    /// 0 for the main thread.
+    id: usize,
+    shared: &'a Shared<'a>,
+    time: TimeManager<SystemClock>,
+    /// Whether the search was pondering when last checked.
+    pondering: bool,
+    signals: &'a Signals,
+    tt: &'a TranspositionTable,
+    rule: RepetitionRule,
+    params: SearchParams,
+    evaluator: Arc<Evaluator>,
+    /// Count of lines with exact values.
+    multi_pv: usize,
+    /// Depth of the current iteration.
+    root_depth: usize,
+    /// Count of nodes searched by this thread.
+    nodes: u64,
+    /// Maximum ply reached.
+    seldepth: usize,
+    history: Box<History>,
+    /// Move being searched at each ply.
+    current_moves: [Move; MAX_PLY + 1],
+}
 
-    for i in 0..moves.size {
-        if keeper.passed() {
-            return vec;
-        }
-        let mut line = Line::new();
-        let mv = moves.at(i).mv;
-        position.do_move(mv, None);
-        let ev = -search_node(position, -beta, -alpha, depth - 1, keeper, &mut line);
-        vec.push((mv, ev, line));
-        position.undo_move(mv);
-        if ev > alpha {
-            alpha = ev;
-        }
-        if alpha >= beta {
-            return vec;
+impl<'a> Searcher<'a> {
+    fn new(
+        id: usize,
+        shared: &'a Shared<'a>,
+        limits: &SearchLimits,
+        options: &SearchOptions,
+        position: &Position,
+    ) -> Self {
+        Searcher {
+            id,
+            shared,
+            time: TimeManager::new(
+                limits,
+                position.side,
+                position.game_ply(),
+                options.move_overhead,
+                SystemClock,
+            ),
+            pondering: shared.signals.ponder.load(Ordering::Relaxed),
+            signals: shared.signals,
+            tt: shared.tt,
+            rule: options.rule,
+            params: options.params,
+            evaluator: evaluator(&options.eval_params),
+            multi_pv: options.multi_pv,
+            root_depth: 0,
+            nodes: 0,
+            seldepth: 0,
+            history: Box::default(),
+            current_moves: [MOVE_NONE; MAX_PLY + 1],
         }
     }
-
-    vec
 }
 
-fn search_node(
-    position: &mut Position,
-    alpha: Value,
-    beta: Value,
-    depth: usize,
-    keeper: &TimeKeeper,
-    pline: &mut Line,
-) -> Value {
-    if keeper.passed() {
-        return 0;
+impl Searcher<'_> {
+    fn stopped(&mut self) -> bool {
+        // The clock starts on ponderhit.
+        if self.pondering && !self.signals.ponder.load(Ordering::Relaxed) {
+            self.pondering = false;
+            self.time.restart();
+        }
+        self.shared.abort.load(Ordering::Relaxed)
+            || self.signals.stop.load(Ordering::Relaxed)
+            || self.shared.max_nodes.is_some_and(|n| self.shared.nodes() >= n)
+            // The main thread stops the helpers when the time is up.
+            || (self.id == 0
+                && !self.signals.ponder.load(Ordering::Relaxed)
+                && self.time.hard_passed())
     }
 
-    if depth <= 0 {
-        pline.size = 0;
-        return qsearch(position, alpha, beta, 0, keeper);
+    /// Counts a node searched by this thread.
+    fn add_node(&mut self) {
+        self.nodes += 1;
+        self.shared.nodes[self.id].store(self.nodes, Ordering::Relaxed);
+    }
+
+    /// Deepens the search iteratively.
+    /// Returns the root moves with the values and the lines, the count of the first moves
+    /// to choose the best move from and the completed depth.
+    fn iterate(
+        &mut self,
+        position: &mut Position,
+        root_moves: Vec<Move>,
+        max_depth: usize,
+        mate_ply: Option<usize>,
+        on_iteration: &mut dyn FnMut(&IterationInfo),
+    ) -> (Vec<(Move, Value, Line)>, usize, usize) {
+        // Root moves ordered by the values of the previous iteration.
+        let mut root_moves = root_moves;
+        // Helper threads with odd ids skip the first depth so that the threads search
+        // different depths at the same time.
+        let mut depth = self.id % 2;
+        let mut result: Vec<(Move, Value, Line)> = Vec::new();
+        // Count of the first moves of the result to choose the best move from.
+        let mut searched = usize::MAX;
+        'iteration: while depth < max_depth {
+            let multi_pv = self.multi_pv.clamp(1, root_moves.len().max(1));
+            // Root moves of this iteration. The first `pv_idx` ones have exact values.
+            let mut lines: Vec<(Move, Value, Line)> = Vec::new();
+            for pv_idx in 0..multi_pv {
+                let prev = result.get(pv_idx).map(|r| r.1);
+                let mut delta = ASPIRATION_DELTA;
+                let (mut alpha, mut beta) = match prev {
+                    Some(v) if depth + 1 >= ASPIRATION_DEPTH && v.abs() < VALUE_WIN_IN_MAX_PLY => {
+                        ((v - delta).max(-VALUE_INF), (v + delta).min(VALUE_INF))
+                    }
+                    _ => (-VALUE_INF, VALUE_INF),
+                };
+                // The moves of the previous lines are excluded.
+                let moves = &root_moves[pv_idx..];
+                let res = loop {
+                    let res = self.search_root(moves, position, alpha, beta, depth + 1);
+                    if self.stopped() {
+                        break res;
+                    }
+                    let best = res.iter().map(|r| r.1).max().unwrap_or(-VALUE_INF);
+                    // Widen the window on a fail-low or fail-high.
+                    if best <= alpha && alpha > -VALUE_INF {
+                        alpha = (alpha as i32 - delta as i32).max(-VALUE_INF as i32) as Value;
+                    } else if best >= beta && beta < VALUE_INF {
+                        beta = (beta as i32 + delta as i32).min(VALUE_INF as i32) as Value;
+                    } else {
+                        break res;
+                    }
+                    delta = delta.saturating_mul(2);
+                };
+                if self.stopped() {
+                    if pv_idx > 0 {
+                        // The first lines of this iteration are complete,
+                        // and the others are taken from the previous iteration.
+                        searched = pv_idx;
+                        lines.truncate(pv_idx);
+                        let rest: Vec<_> = result
+                            .into_iter()
+                            .filter(|r| lines.iter().all(|l| l.0 != r.0))
+                            .collect();
+                        result = lines;
+                        result.extend(rest);
+                        break 'iteration;
+                    }
+                    let values: Vec<_> = res.iter().map(|r| (r.0, r.1)).collect();
+                    if result.is_empty() {
+                        // Use the unfinished first iteration rather than nothing.
+                        result = res;
+                    } else if let Some(best) = partial_best_move(&values, best_move(&result), alpha)
+                    {
+                        // The best move of the unfinished iteration is not worse than
+                        // the previous one. The other lines are taken from the previous iteration.
+                        let mut res = res;
+                        res.retain(|r| r.0 == best);
+                        searched = 1;
+                        let rest: Vec<_> = result.into_iter().filter(|r| r.0 != best).collect();
+                        result = res;
+                        result.extend(rest);
+                    }
+                    break 'iteration;
+                }
+                let mut res = res;
+                // The sort is stable, so the earlier move is preferred among equal values.
+                res.sort_by_key(|r| std::cmp::Reverse(r.1));
+                lines.truncate(pv_idx);
+                lines.extend(res);
+                root_moves = lines.iter().map(|r| r.0).collect();
+            }
+            // A later line may get a higher value by the instability of the search.
+            let exact = multi_pv.min(lines.len());
+            lines[..exact].sort_by_key(|r| std::cmp::Reverse(r.1));
+            root_moves = lines.iter().map(|r| r.0).collect();
+            result = lines;
+            depth += 1;
+            // Only the main thread reports the progress.
+            if self.id == 0 {
+                let time = self.time.elapsed();
+                let nodes = self.shared.nodes();
+                for (i, (mv, value, line)) in result.iter().take(multi_pv).enumerate() {
+                    let mut side = position.side;
+                    let mut pv = Vec::new();
+                    for mv in std::iter::once(mv).chain(line.moves()) {
+                        pv.push(move_to_mfen(*mv, side));
+                        side = !side;
+                    }
+                    on_iteration(&IterationInfo {
+                        depth,
+                        multipv: i + 1,
+                        seldepth: self.seldepth,
+                        score: Score::from(*value),
+                        nodes,
+                        nps: nodes * 1000 / time.max(1),
+                        time,
+                        hashfull: self.tt.hashfull(),
+                        pv,
+                    });
+                }
+            }
+            if let Some((mv, value, _)) = result.first() {
+                if mate_ply.is_some_and(|n| value_to_mate(*value).is_some_and(|m| m <= n)) {
+                    break;
+                }
+                // The time is not limited while pondering.
+                if self.id == 0
+                    && self.time.iteration_done(*mv, *value)
+                    && !self.signals.ponder.load(Ordering::Relaxed)
+                {
+                    break;
+                }
+            }
+        }
+        (result, searched, depth)
     }
 
-    let mut line = Line::new();
+    fn search_root(
+        &mut self,
+        moves: &[Move],
+        position: &mut Position,
+        alpha: Value,
+        beta: Value,
+        depth: usize,
+    ) -> Vec<(Move, Value, Line)> {
+        let mut vec = Vec::new();
+        self.root_depth = depth;
 
-    let mut bestvalue = -VALUE_INF;
-    let mut alpha = alpha;
+        let mut alpha = alpha;
 
-    let mut picker = MovePicker::new(position);
-    let mut move_count = 0;
-    loop {
-        let mv = picker.next_move(position);
-        if let Some(mv) = mv {
-            if !position.is_legal(mv) {
-                continue;
+        for (i, &mv) in moves.iter().enumerate() {
+            if self.stopped() {
+                return vec;
             }
-            move_count += 1;
-
+            let mut line = Line::new();
+            self.current_moves[0] = mv;
             position.do_move(mv, None);
-            let ev = -search_node(position, -beta, -alpha, depth - 1, keeper, &mut line);
+            let ev = self.search_pvs(position, alpha, beta, depth - 1, 0, 1, i == 0, &mut line);
             position.undo_move(mv);
-
-            if ev > bestvalue {
-                bestvalue = ev;
+            // The value of an interrupted move is unreliable.
+            if self.stopped() {
+                return vec;
             }
+            vec.push((mv, ev, line));
             if ev > alpha {
                 alpha = ev;
-                if alpha < beta {
-                    unsafe {
-                        *pline.moves.get_unchecked_mut(0).as_mut_ptr() = mv;
-                    }
-                    pline.moves[1..line.size + 1].copy_from_slice(&line.moves[..line.size]);
-                    pline.size = line.size + 1;
-                }
             }
             if alpha >= beta {
-                break;
+                return vec;
             }
-        } else {
-            break;
         }
-    }
 
-    if move_count == 0 {
-        -VALUE_WIN
-    } else {
-        bestvalue
+        vec
     }
-}
 
-fn qsearch(
-    position: &mut Position,
-    alpha: Value,
-    beta: Value,
-    ply: usize,
-    keeper: &TimeKeeper,
-) -> Value {
-    if keeper.passed() || ply >= MAX_PLY {
-        return 0;
+    /// Searches the child node after a move with principal variation search.
+    /// Moves other than the first one are searched with a null window first,
+    /// and searched again with the full window only if they raise alpha.
+    /// A move with a `reduction` is searched again at full depth if the reduced search raises alpha.
+    /// Returns the value from the point of view of the parent.
+    #[allow(clippy::too_many_arguments)]
+    fn search_pvs(
+        &mut self,
+        position: &mut Position,
+        alpha: Value,
+        beta: Value,
+        depth: usize,
+        reduction: usize,
+        ply: usize,
+        first: bool,
+        line: &mut Line,
+    ) -> Value {
+        if !first {
+            if reduction > 0 {
+                let ev =
+                    -self.search_node(position, -alpha - 1, -alpha, depth - reduction, ply, line);
+                if ev <= alpha {
+                    return ev;
+                }
+            }
+            if beta as i32 - alpha as i32 > 1 {
+                let ev = -self.search_node(position, -alpha - 1, -alpha, depth, ply, line);
+                if ev <= alpha || ev >= beta {
+                    return ev;
+                }
+            }
+        }
+        -self.search_node(position, -beta, -alpha, depth, ply, line)
     }
 
-    // Pruning by an evaluation.
-    let stand_pat = eval(position);
-    if stand_pat >= beta {
-        return stand_pat;
-    }
-    let mut alpha = alpha;
-    if stand_pat > alpha {
-        alpha = stand_pat;
+    /// Returns the reduction of a late quiet move.
+    fn reduction(&self, depth: usize, move_count: usize, pv: bool, history: i32) -> usize {
+        let params = &self.params;
+        if !params.lmr || depth < params.lmr_depth || move_count <= params.lmr_moves {
+            return 0;
+        }
+        let r = (depth as f64).ln() * (move_count as f64).ln() * params.lmr_scale as f64 / 1024.0;
+        let r = r as i32 - pv as i32 - history / params.lmr_history.max(1);
+        // At least one ply is left to search.
+        r.clamp(0, depth.saturating_sub(2) as i32) as usize
     }
 
-    let mut bestvalue = -VALUE_INF;
+    fn search_node(
+        &mut self,
+        position: &mut Position,
+        alpha: Value,
+        beta: Value,
+        depth: usize,
+        ply: usize,
+        pline: &mut Line,
+    ) -> Value {
+        if self.stopped() {
+            return 0;
+        }
+        if let Some(value) = self.rule.value(position.repetition_state(), ply) {
+            self.add_node();
+            pline.size = 0;
+            return value;
+        }
 
-    let mut picker = MovePicker::qsearch();
-    let mut move_count = 0;
-    loop {
-        let mv = picker.next_move(position);
-        if let Some(mv) = mv {
-            if !position.is_legal(mv) {
-                continue;
-            }
-            move_count += 1;
+        // The node at the horizon is counted by qsearch.
+        if depth == 0 {
+            pline.size = 0;
+            return self.qsearch(position, alpha, beta, ply);
+        }
 
-            position.do_move(mv, None);
-            let ev = -qsearch(position, -beta, -alpha, ply + 1, keeper);
-            position.undo_move(mv);
+        self.add_node();
+        self.seldepth = self.seldepth.max(ply);
 
-            if ev > bestvalue {
-                bestvalue = ev;
+        let key = position.key();
+        let pv = beta as i32 - alpha as i32 > 1;
+        let tt_entry = self.tt.probe(key);
+        let tt_move = tt_entry.map_or(MOVE_NONE, |e| e.mv);
+        // A cutoff in a PV node would cut the principal variation.
+        if let Some(entry) = tt_entry.filter(|_| !pv) {
+            let value = value_from_tt(entry.value, ply);
+            if entry.depth >= depth
+                && match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => value >= beta,
+                    Bound::Upper => value <= alpha,
+                    Bound::None => false,
+                }
+            {
+                pline.size = 0;
+                return value;
             }
-            if ev > alpha {
-                alpha = ev;
+        }
+
+        let mut line = Line::new();
+
+        let params = self.params;
+        let in_check = position.checkers() != 0;
+        let static_eval = if in_check {
+            -VALUE_INF
+        } else {
+            self.evaluator.eval(position)
+        };
+        let prev = self.current_moves[ply - 1];
+        // Pruning by the static evaluation is unsafe close to the mate values.
+        let prunable = !pv
+            && !in_check
+            && static_eval.abs() < VALUE_WIN_IN_MAX_PLY
+            && beta.abs() < VALUE_WIN_IN_MAX_PLY;
+
+        if params.reverse_futility
+            && prunable
+            && depth <= params.reverse_futility_depth
+            && static_eval as i32 - params.reverse_futility_margin as i32 * depth as i32
+                >= beta as i32
+        {
+            pline.size = 0;
+            return static_eval;
+        }
+
+        // Two null moves in a row are not tried.
+        if params.null_move
+            && prunable
+            && depth >= params.null_move_depth
+            && prev != MOVE_NONE
+            && static_eval >= beta
+            && !zugzwang_prone(position)
+        {
+            let r = params.null_move_reduction + depth / 4;
+            self.current_moves[ply] = MOVE_NONE;
+            position.do_null_move();
+            let ev = -self.search_node(
+                position,
+                -beta,
+                -beta + 1,
+                depth.saturating_sub(r + 1),
+                ply + 1,
+                &mut line,
+            );
+            position.undo_null_move();
+            if ev >= beta && !self.stopped() {
+                pline.size = 0;
+                // A mate found after passing the turn is not proven.
+                return if ev >= VALUE_WIN_IN_MAX_PLY { beta } else { ev };
             }
-            if alpha >= beta {
+        }
+
+        let mut bestvalue = -VALUE_INF;
+        let mut bestmove = MOVE_NONE;
+        let old_alpha = alpha;
+        let mut alpha = alpha;
+
+        let mut picker = MovePicker::new(
+            position,
+            tt_move,
+            self.history.killers(ply),
+            self.history.countermove(position, prev),
+        );
+        let mut move_count = 0;
+        // Quiet moves searched without a cutoff.
+        let mut quiets = [MOVE_NONE; QUIETS_NB];
+        let mut quiet_count = 0;
+        loop {
+            let mv = picker.next_move(position, &self.history);
+            if let Some(mv) = mv {
+                if !position.is_legal(mv) {
+                    continue;
+                }
+                move_count += 1;
+
+                let quiet = is_quiet(mv);
+                // Computed before the move, which changes the side to move.
+                let history = if quiet {
+                    self.history.score(position, mv)
+                } else {
+                    0
+                };
+                let gives_check = position.gives_check(mv);
+
+                let futility_value =
+                    static_eval as i32 + params.futility_margin as i32 * depth as i32;
+                if params.futility
+                    && prunable
+                    && quiet
+                    && !gives_check
+                    && move_count > 1
+                    && depth <= params.futility_depth
+                    && futility_value <= alpha as i32
+                {
+                    bestvalue = bestvalue.max(futility_value as Value);
+                    continue;
+                }
+
+                self.current_moves[ply] = mv;
+                position.do_move(mv, Some(gives_check));
+                // The extension is limited so that the line fits in MAX_PLY.
+                let extension = (params.check_extension
+                    && gives_check
+                    && ply < (2 * self.root_depth).min(MAX_PLY - 1))
+                    as usize;
+                let reduction = if quiet && !in_check && !gives_check {
+                    self.reduction(depth, move_count, pv, history)
+                } else {
+                    0
+                };
+                let ev = self.search_pvs(
+                    position,
+                    alpha,
+                    beta,
+                    depth - 1 + extension,
+                    reduction,
+                    ply + 1,
+                    move_count == 1,
+                    &mut line,
+                );
+                position.undo_move(mv);
+
+                if ev > bestvalue {
+                    bestvalue = ev;
+                }
+                if ev > alpha {
+                    alpha = ev;
+                    bestmove = mv;
+                    if alpha < beta {
+                        unsafe {
+                            *pline.moves.get_unchecked_mut(0).as_mut_ptr() = mv;
+                        }
+                        pline.moves[1..line.size + 1].copy_from_slice(&line.moves[..line.size]);
+                        pline.size = line.size + 1;
+                    }
+                }
+                if alpha >= beta {
+                    if quiet {
+                        self.history
+                            .update(position, ply, prev, mv, &quiets[..quiet_count], depth);
+                    }
+                    break;
+                }
+                if quiet && quiet_count < QUIETS_NB {
+                    quiets[quiet_count] = mv;
+                    quiet_count += 1;
+                }
+            } else {
                 break;
             }
-        } else {
-            break;
         }
+
+        if move_count == 0 {
+            bestvalue = lose_in(ply);
+        }
+
+        // The result of an interrupted search is unreliable.
+        if !self.stopped() {
+            let bound = if bestvalue >= beta {
+                Bound::Lower
+            } else if bestvalue > old_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+            self.tt
+                .store(key, bestmove, value_to_tt(bestvalue, ply), depth, bound);
+        }
+
+        bestvalue
     }
 
-    if move_count == 0 {
-        if position.checkers() != 0 {
-            -VALUE_WIN
-        } else {
-            stand_pat
+    fn qsearch(&mut self, position: &mut Position, alpha: Value, beta: Value, ply: usize) -> Value {
+        if self.stopped() || ply >= MAX_PLY {
+            return 0;
         }
-    } else {
+        self.add_node();
+        self.seldepth = self.seldepth.max(ply);
+
+        let key = position.key();
+        let tt_entry = self.tt.probe(key);
+        let tt_move = tt_entry.map_or(MOVE_NONE, |e| e.mv);
+        if let Some(entry) = tt_entry {
+            let value = value_from_tt(entry.value, ply);
+            if match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => value >= beta,
+                Bound::Upper => value <= alpha,
+                Bound::None => false,
+            } {
+                return value;
+            }
+        }
+
+        // Pruning by an evaluation. All evasions are searched when in check.
+        let in_check = position.checkers() != 0;
+        let stand_pat = self.evaluator.eval(position);
+        if !in_check && stand_pat >= beta {
+            return stand_pat;
+        }
+        let old_alpha = alpha;
+        let mut alpha = alpha;
+        if !in_check && stand_pat > alpha {
+            alpha = stand_pat;
+        }
+
+        // The stand pat is a lower bound of the value unless in check.
+        let mut bestvalue = if in_check { -VALUE_INF } else { stand_pat };
+        let mut bestmove = MOVE_NONE;
+
+        let mut picker = MovePicker::qsearch(position, tt_move);
+        let mut move_count = 0;
+        loop {
+            let mv = picker.next_move(position, &self.history);
+            if let Some(mv) = mv {
+                // Captures losing material do not improve on the stand pat.
+                if !in_check && !position.see(mv, 0) {
+                    continue;
+                }
+                if !position.is_legal(mv) {
+                    continue;
+                }
+                move_count += 1;
+
+                position.do_move(mv, Some(position.gives_check(mv)));
+                let ev = -self.qsearch(position, -beta, -alpha, ply + 1);
+                position.undo_move(mv);
+
+                if ev > bestvalue {
+                    bestvalue = ev;
+                }
+                if ev > alpha {
+                    alpha = ev;
+                    bestmove = mv;
+                }
+                if alpha >= beta {
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+
+        if in_check && move_count == 0 {
+            bestvalue = lose_in(ply);
+        }
+
+        if !self.stopped() {
+            let bound = if bestvalue >= beta {
+                Bound::Lower
+            } else if bestvalue > old_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+            self.tt
+                .store(key, bestmove, value_to_tt(bestvalue, ply), 0, bound);
+        }
+
         bestvalue
     }
 }
@@ -0,0 +1,157 @@
+use crate::{
+    bitboard::KG_BITBOARD,
+    change_bit,
+    eval::PIECE_VALUES,
+    foreach_bb,
+    position::Position,
+    types::{
+        bit, get_capture, get_from, get_move_type, get_to, Bitboard, Move, MoveType, PieceType,
+        Side, Square, Value, RANK_NB,
+    },
+};
+
+/// Returns the change of the material by capturing a piece of the type.
+/// The captured piece goes to the hand of the capturer, so it counts twice.
+fn capture_value(pt: PieceType) -> i32 {
+    2 * PIECE_VALUES[pt as usize] as i32
+}
+
+/// Attacker found by `least_valuable_attacker`.
+struct Attacker {
+    sq: Square,
+    /// Type of the piece left on the target square.
+    pt: PieceType,
+    /// The attacker shoots an arrow instead of moving.
+    shoots: bool,
+}
+
+impl Position {
+    /// Returns true if the static exchange evaluation of the move is at least `threshold`.
+    /// Moves other than captures are evaluated as 0.
+    pub fn see(&self, mv: Move, threshold: Value) -> bool {
+        self.see_value(mv) >= threshold as i32
+    }
+
+    fn see_value(&self, mv: Move) -> i32 {
+        let typ = get_move_type(mv);
+        if typ != MoveType::Normal && typ != MoveType::Shoot {
+            return 0;
+        }
+        let to = get_to(mv);
+        let from = get_from(mv);
+        let mut occupied = self.pieces();
+        // Archers which have shot once and twice in the exchange.
+        let mut shot = [0; 2];
+
+        let mut gain = [0; 64];
+        gain[0] = capture_value(get_capture(mv));
+        let mut on_target = if typ == MoveType::Shoot {
+            change_bit!(shot[0], from as usize);
+            PieceType::Arrow
+        } else {
+            change_bit!(occupied, from as usize);
+            self.grid[from as usize].pt()
+        };
+        let mut side = !self.side;
+        let mut d = 0;
+        while d + 1 < gain.len() {
+            let Some(attacker) = self.least_valuable_attacker(to, side, occupied, &shot) else {
+                break;
+            };
+            // The crown cannot capture a defended piece.
+            if attacker.sq == self.crown_sq(side)
+                && self
+                    .least_valuable_attacker(
+                        to,
+                        !side,
+                        occupied ^ (1 << attacker.sq as usize),
+                        &shot,
+                    )
+                    .is_some()
+            {
+                break;
+            }
+            d += 1;
+            gain[d] = capture_value(on_target) - gain[d - 1];
+            if attacker.shoots {
+                if bit(shot[0], attacker.sq as usize) == 1 {
+                    change_bit!(shot[1], attacker.sq as usize);
+                } else {
+                    change_bit!(shot[0], attacker.sq as usize);
+                }
+            } else {
+                change_bit!(occupied, attacker.sq as usize);
+            }
+            on_target = attacker.pt;
+            side = !side;
+        }
+        // Each side may stop capturing if it loses by going on.
+        while d > 0 {
+            gain[d - 1] = -(-gain[d - 1]).max(gain[d]);
+            d -= 1;
+        }
+        gain[0]
+    }
+
+    /// Returns the attacker of `side` to `to` which leaves the least valuable piece there.
+    fn least_valuable_attacker(
+        &self,
+        to: Square,
+        side: Side,
+        occupied: Bitboard,
+        shot: &[Bitboard; 2],
+    ) -> Option<Attacker> {
+        let mut best: Option<Attacker> = None;
+        let mut consider = |attacker: Attacker| {
+            if best
+                .as_ref()
+                .is_none_or(|b| PIECE_VALUES[attacker.pt as usize] < PIECE_VALUES[b.pt as usize])
+            {
+                best = Some(attacker);
+            }
+        };
+        foreach_bb!(self.pieces_side(side) & occupied, sq, {
+            let p = self.grid[sq as usize];
+            let pt = p.pt();
+            if bit(KG_BITBOARD.movable_sq[p as usize][sq as usize], to as usize) == 1 {
+                consider(Attacker {
+                    sq,
+                    pt,
+                    shoots: false,
+                });
+            }
+            if pt == PieceType::Heavy {
+                // Two squares forward over an empty square.
+                let (mid, target) = if side == Side::Black {
+                    (sq as usize + RANK_NB, sq as usize + RANK_NB * 2)
+                } else {
+                    (
+                        (sq as usize).wrapping_sub(RANK_NB),
+                        (sq as usize).wrapping_sub(RANK_NB * 2),
+                    )
+                };
+                if target == to as usize && bit(occupied, mid) == 0 {
+                    consider(Attacker {
+                        sq,
+                        pt,
+                        shoots: false,
+                    });
+                }
+            }
+            let shots = match pt {
+                PieceType::Archer1 => 1,
+                PieceType::Archer2 => 2,
+                _ => 0,
+            } - bit(shot[0], sq as usize)
+                - bit(shot[1], sq as usize);
+            if shots > 0 && bit(KG_BITBOARD.arrow_attacks(occupied, sq), to as usize) == 1 {
+                consider(Attacker {
+                    sq,
+                    pt: PieceType::Arrow,
+                    shoots: true,
+                });
+            }
+        });
+        best
+    }
+}
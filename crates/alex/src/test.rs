@@ -1,16 +1,31 @@
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
+    use std::{cell::Cell, collections::HashSet, fs, str::FromStr};
 
     use num_traits::FromPrimitive;
     use rand::{Rng, SeedableRng};
     use rand_xoshiro::Xoshiro256StarStar;
 
     use crate::{
+        book::{Book, BookError},
+        eval::{evaluator, EvalParams, EvalParamsError},
+        game::{DrawReason, GameResult, GameResultError, WinReason},
+        kifu::{Kifu, KifuError, KifuMove},
+        mate::{self, MateLimits, MateResult},
         movegen::{GenType, MoveList},
-        position::Position,
+        options::{EngineOptions, OptionError, OPTIONS},
+        perft,
+        position::{MfenError, Position, RepetitionState, STARTPOS},
+        search::{
+            partial_best_move, search, RepetitionRule, Score, SearchLimits, SearchOptions,
+            SearchParams, Signals,
+        },
+        timeman::{Clock, TimeManager},
+        tt::{value_from_tt, value_to_tt, Bound, TranspositionTable},
         types::{
-            bit, move_to_mfen, PieceType, Side, Square, PIECE_TYPE_NB, RANK_NB, SIDE_NB, SQUARE_NB,
+            bit, get_capture, get_move_type, is_demise, lose_in, make_move_normal, move_to_mfen,
+            win_in, MoveError, PieceType, Side, Square, MOVE_DEMISE, MOVE_NONE, PIECE_TYPE_NB,
+            RANK_NB, SIDE_NB, SQUARE_NB,
         },
     };
 
@@ -35,8 +50,8 @@ mod tests {
             println!("White crown lost.");
             return false;
         }
-        if count_piece(&position) != position.piece_count {
-            println!("grid  : {:?}", count_piece(&position));
+        if count_piece(position) != position.piece_count {
+            println!("grid  : {:?}", count_piece(position));
             println!("pieces: {:?}", position.piece_count);
             println!("Pieces mismatch");
             return false;
@@ -54,9 +69,7 @@ mod tests {
                 println!("Sides failed at {}", Square::from_usize(i).unwrap());
                 return false;
             }
-            if position.piece_list[side as usize][pt as usize][position.index[i] as usize] as usize
-                != i
-            {
+            if position.piece_list[side as usize][pt as usize][position.index[i]] as usize != i {
                 println!("Piece list failed at {}", Square::from_usize(i).unwrap());
                 return false;
             }
@@ -132,6 +145,9 @@ mod tests {
         if pos1.piece_count != pos2.piece_count {
             return false;
         }
+        if pos1.key() != pos2.key() {
+            return false;
+        }
         true
     }
 
@@ -187,6 +203,12 @@ mod tests {
                 println!("mv: {}", mv_mfen);
                 panic!("Check failed");
             }
+            if position.key() != position.compute_key() {
+                println!("old: {}", temp);
+                println!("new: {}", position);
+                println!("mv: {}", mv_mfen);
+                panic!("Key failed");
+            }
             position.undo_move(mv);
             if !equals(&position, &temp) {
                 println!("old: {}", temp);
@@ -215,4 +237,1211 @@ mod tests {
             random_move_once(&mut rng, i);
         }
     }
+
+    #[test]
+    fn tt_store_probe() {
+        let tt = TranspositionTable::new(1);
+        tt.new_search();
+        let key = 0x0123456789ABCDEF;
+        assert!(tt.probe(key).is_none());
+
+        let mv = make_move_normal(PieceType::Light, Square::A2, Square::A3);
+        tt.store(key, mv, -1234, 7, Bound::Lower);
+        let entry = tt.probe(key).unwrap();
+        assert_eq!(entry.mv, mv);
+        assert_eq!(entry.value, -1234);
+        assert_eq!(entry.depth, 7);
+        assert_eq!(entry.bound, Bound::Lower);
+        assert!(tt.probe(key ^ 1).is_none());
+
+        // The old move is kept when a new entry has no move.
+        tt.store(key, MOVE_NONE, 10, 8, Bound::Exact);
+        assert_eq!(tt.probe(key).unwrap().mv, mv);
+
+        for ply in [0, 1, 10, 100] {
+            for value in [win_in(ply + 3), lose_in(ply + 5), 0, -500, 1500] {
+                assert_eq!(value_from_tt(value_to_tt(value, ply), ply), value);
+            }
+        }
+        assert_eq!(value_from_tt(value_to_tt(win_in(9), 4), 2), win_in(7));
+    }
+
+    fn play(position: &mut Position, moves: &[&str]) {
+        for m in moves {
+            let mv = position.read_move(m.to_string()).unwrap();
+            assert!(
+                position.is_pseudo_legal(mv) && position.is_legal(mv),
+                "{}",
+                m
+            );
+            position.do_move(mv, None);
+        }
+    }
+
+    #[test]
+    fn repetition() {
+        let mut position =
+            Position::from_str("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0").unwrap();
+        play(&mut position, &["B1A3", "B8A6", "A3B1"]);
+        assert_eq!(position.repetition_state(), RepetitionState::None);
+        play(&mut position, &["A6B8"]);
+        assert_eq!(position.repetition_state(), RepetitionState::Draw);
+
+        // Black checks the white king with the knight on every move.
+        let mut position = Position::from_str("k7/2N5/8/8/8/8/8/7K w - 0 0").unwrap();
+        play(&mut position, &["A8B8", "C7A6", "B8A8"]);
+        assert_eq!(position.repetition_state(), RepetitionState::None);
+        play(&mut position, &["A6C7"]);
+        assert_eq!(position.repetition_state(), RepetitionState::Win);
+        play(&mut position, &["A8B8"]);
+        assert_eq!(position.repetition_state(), RepetitionState::Lose);
+    }
+
+    #[test]
+    fn game_result() {
+        let startpos = "bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0";
+        let position = Position::from_str(startpos).unwrap();
+        assert_eq!(position.game_result(None), GameResult::Ongoing);
+        assert_eq!(
+            position.game_result(Some(0)),
+            GameResult::Draw(DrawReason::MoveLimit)
+        );
+
+        let position = Position::from_str("k7/G7/8/1N6/8/8/8/7K w - 0 0").unwrap();
+        assert_eq!(
+            position.game_result(None),
+            GameResult::Win(Side::Black, WinReason::Checkmate)
+        );
+        let position = Position::from_str("k7/8/1LN5/8/8/8/8/7K w - 0 0").unwrap();
+        assert_eq!(
+            position.game_result(None),
+            GameResult::Win(Side::Black, WinReason::NoLegalMoves)
+        );
+
+        let mut position = Position::from_str(startpos).unwrap();
+        for _ in 0..2 {
+            play(&mut position, &["B1A3", "B8A6", "A3B1", "A6B8"]);
+        }
+        assert_eq!(position.game_result(None), GameResult::Ongoing);
+        play(&mut position, &["B1A3", "B8A6", "A3B1", "A6B8"]);
+        assert_eq!(
+            position.game_result(None),
+            GameResult::Draw(DrawReason::Repetition)
+        );
+
+        let mut position = Position::from_str("k7/2N5/8/8/8/8/8/7K w - 0 0").unwrap();
+        for _ in 0..3 {
+            play(&mut position, &["A8B8", "C7A6", "B8A8", "A6C7"]);
+        }
+        assert_eq!(
+            position.game_result(None),
+            GameResult::Win(Side::White, WinReason::PerpetualCheck)
+        );
+    }
+
+    #[test]
+    fn iteration_info() {
+        assert_eq!(Score::from(win_in(3)), Score::Mate(3));
+        assert_eq!(Score::from(lose_in(2)), Score::Mate(-2));
+        assert_eq!(Score::from(-150), Score::Cp(-150));
+
+        let mut position = Position::from_str("k7/8/G7/1N6/8/8/8/7K b - 0 0").unwrap();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let mut infos = Vec::new();
+        search(
+            &mut position,
+            &limits,
+            &tt,
+            &SearchOptions::default(),
+            &Signals::default(),
+            &mut |info| infos.push(info.clone()),
+        );
+        assert_eq!(infos.len(), 3);
+        for (i, info) in infos.iter().enumerate() {
+            assert_eq!(info.depth, i + 1);
+            assert!(info.seldepth >= 1);
+            assert!(info.nodes > 0);
+        }
+        let last = infos.last().unwrap();
+        assert_eq!(last.score, Score::Mate(1));
+        assert_eq!(last.pv, vec!["A6A7".to_string()]);
+    }
+
+    #[test]
+    fn multi_pv() {
+        let mut position =
+            Position::from_str("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0").unwrap();
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let options = SearchOptions {
+            multi_pv: 3,
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let mut infos = Vec::new();
+        let info = search(
+            &mut position,
+            &limits,
+            &tt,
+            &options,
+            &Signals::default(),
+            &mut |info| infos.push(info.clone()),
+        )
+        .unwrap();
+        assert_eq!(infos.len(), 4 * 3);
+        let last = &infos[infos.len() - 3..];
+        for (i, line) in last.iter().enumerate() {
+            assert_eq!(line.depth, 4);
+            assert_eq!(line.multipv, i + 1);
+            let (mv, value, pv) = &info.root_moves[i];
+            assert_eq!(line.pv[0], move_to_mfen(*mv, position.side));
+            assert_eq!(line.score, Score::from(*value));
+            assert_eq!(line.pv.len(), pv.len() + 1);
+        }
+        assert!(last.windows(2).all(|w| w[0].pv[0] != w[1].pv[0]));
+        assert_eq!(last[0].pv[0], move_to_mfen(info.mv, position.side));
+        assert!(info.root_moves[..3].windows(2).all(|w| w[0].1 >= w[1].1));
+    }
+
+    #[test]
+    fn engine_options() {
+        let mut options = EngineOptions::default();
+        assert_eq!(options.hash, 16);
+        assert_eq!(options.multi_pv, 1);
+        assert!(options.ponder);
+
+        assert_eq!(options.set("hash", Some("64")), Ok("Hash"));
+        assert_eq!(options.hash, 64);
+        assert_eq!(options.set("Ponder", Some("false")), Ok("Ponder"));
+        assert!(!options.ponder);
+        assert_eq!(options.set("BookFile", Some("book.txt")), Ok("BookFile"));
+        assert_eq!(options.book_file, "book.txt");
+        assert_eq!(options.set("BookFile", Some("<empty>")), Ok("BookFile"));
+        assert_eq!(options.book_file, "");
+
+        assert_eq!(
+            options.set("Foo", Some("1")),
+            Err(OptionError::UnknownName("Foo".to_string()))
+        );
+        assert_eq!(
+            options.set("Threads", Some("x")),
+            Err(OptionError::InvalidValue("Threads", "x".to_string()))
+        );
+        assert_eq!(
+            options.set("SkillLevel", Some("21")),
+            Err(OptionError::OutOfRange("SkillLevel", 21, 0, 20))
+        );
+        assert_eq!(
+            options.set("Ponder", None),
+            Err(OptionError::MissingValue("Ponder"))
+        );
+        assert_eq!(options.search_options().threads, 1);
+
+        assert_eq!(options.search_options().rule, RepetitionRule::Sennichite);
+        assert_eq!(
+            options.set("RepetitionRule", Some("draw")),
+            Ok("RepetitionRule")
+        );
+        assert_eq!(options.search_options().rule, RepetitionRule::Draw);
+        assert_eq!(
+            options.set("RepetitionRule", Some("never")),
+            Err(OptionError::InvalidValue(
+                "RepetitionRule",
+                "never".to_string()
+            ))
+        );
+        assert_eq!(
+            OPTIONS.last().unwrap().to_string(),
+            "option name RepetitionRule type combo default Sennichite var Sennichite var Draw"
+        );
+    }
+
+    #[test]
+    fn eval_params() {
+        // The tables are rebuilt when the parameters change.
+        let position =
+            Position::from_str("r3la2/2akh1lh/l3g2l/HNnlrhl1/p4HLL/GHN1h3/2Ph1RA1/ARGG3n b - 1 0")
+                .unwrap();
+        let params = EvalParams {
+            demise: 0,
+            ..Default::default()
+        };
+        let value = evaluator(&params).eval(&position);
+        let default = evaluator(&EvalParams::default()).eval(&position);
+        assert_eq!(value - default, EvalParams::default().demise);
+        assert_eq!(evaluator(&params).eval(&position), value);
+    }
+
+    fn search_with(mfen: &str, limits: &SearchLimits) -> (String, u64) {
+        search_with_options(mfen, limits, &SearchOptions::default())
+    }
+
+    fn search_with_options(
+        mfen: &str,
+        limits: &SearchLimits,
+        options: &SearchOptions,
+    ) -> (String, u64) {
+        let mut position = Position::from_str(mfen).unwrap();
+        let tt = TranspositionTable::new(1);
+        let info = search(
+            &mut position,
+            limits,
+            &tt,
+            options,
+            &Signals::default(),
+            &mut |_| {},
+        )
+        .unwrap();
+        (move_to_mfen(info.mv, position.side), info.nodes)
+    }
+
+    /// Checks the searches of a capture, a position of two kings and a mate in one.
+    fn check_basic_searches(options: &SearchOptions) {
+        let depth = |d| SearchLimits {
+            depth: Some(d),
+            ..Default::default()
+        };
+        let capture = "k7/8/8/8/3n4/3G4/8/7K b - 0 0";
+        assert_eq!(search_with_options(capture, &depth(4), options).0, "D3D4");
+        // The root and the three moves of the king, each counted once.
+        let kings = "k7/8/8/8/8/8/8/7K b - 0 0";
+        assert_eq!(search_with_options(kings, &depth(1), options).1, 4);
+        let mate = "k7/8/G7/1N6/8/8/8/7K b - 0 0";
+        assert_eq!(search_with_options(mate, &depth(3), options).0, "A6A7");
+    }
+
+    #[test]
+    fn fixed_limits() {
+        let depth = |d| SearchLimits {
+            depth: Some(d),
+            ..Default::default()
+        };
+        let nodes = |n| SearchLimits {
+            nodes: Some(n),
+            ..Default::default()
+        };
+
+        check_basic_searches(&SearchOptions::default());
+
+        let capture = "k7/8/8/8/3n4/3G4/8/7K b - 0 0";
+        assert_eq!(
+            search_with(capture, &nodes(3000)),
+            ("D3D4".to_string(), 3000)
+        );
+
+        let mate = "k7/8/G7/1N6/8/8/8/7K b - 0 0";
+        let limits = SearchLimits {
+            mate: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(search_with(mate, &limits).0, "A6A7");
+
+        // The same limits give the same result.
+        let startpos = "bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0";
+        assert_eq!(
+            search_with(startpos, &depth(3)),
+            search_with(startpos, &depth(3))
+        );
+        assert_eq!(
+            search_with(startpos, &nodes(10000)),
+            search_with(startpos, &nodes(10000))
+        );
+    }
+
+    #[test]
+    fn partial_iteration() {
+        let a = make_move_normal(PieceType::None, Square::A2, Square::A3);
+        let b = make_move_normal(PieceType::None, Square::B2, Square::B3);
+        // Moves failing low only have upper bounds, so they do not replace the previous best.
+        assert_eq!(partial_best_move(&[(a, -50), (b, -60)], Some(a), -40), None);
+        assert_eq!(partial_best_move(&[(a, -50), (b, -40)], Some(a), -40), None);
+        // An exact value or a fail-high is accepted.
+        assert_eq!(
+            partial_best_move(&[(a, -50), (b, 30)], Some(a), -40),
+            Some(b)
+        );
+        assert_eq!(partial_best_move(&[(a, 100)], Some(a), -40), Some(a));
+        // The previous best move must have been searched again.
+        assert_eq!(partial_best_move(&[(b, 30)], Some(a), -40), None);
+    }
+
+    #[test]
+    fn selective_search() {
+        let startpos = "bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0";
+        let mut position = Position::from_str(startpos).unwrap();
+        position.do_null_move();
+        assert_eq!(position.side, Side::White);
+        assert_eq!(position.key(), position.compute_key());
+        position.undo_null_move();
+        assert!(equals(&position, &Position::from_str(startpos).unwrap()));
+
+        let depth = |d| SearchLimits {
+            depth: Some(d),
+            ..Default::default()
+        };
+        let full_width = SearchOptions {
+            params: SearchParams::none(),
+            ..Default::default()
+        };
+        // Moves are reduced even at the lowest depths.
+        let reduced = SearchOptions {
+            params: SearchParams {
+                lmr_depth: 0,
+                lmr_moves: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for options in [SearchOptions::default(), full_width.clone(), reduced] {
+            check_basic_searches(&options);
+        }
+        assert!(
+            search_with(startpos, &depth(6)).1
+                < search_with_options(startpos, &depth(6), &full_width).1
+        );
+    }
+
+    #[test]
+    fn lazy_smp() {
+        let options = SearchOptions {
+            threads: 4,
+            ..Default::default()
+        };
+        let depth = |d| SearchLimits {
+            depth: Some(d),
+            ..Default::default()
+        };
+        // The main thread decides the best move.
+        let capture = "k7/8/8/8/3n4/3G4/8/7K b - 0 0";
+        assert_eq!(search_with_options(capture, &depth(5), &options).0, "D3D4");
+        // The main thread searches the root and the three moves of the king, and the helpers
+        // add the nodes they search before the main thread stops them.
+        let kings = "k7/8/8/8/8/8/8/7K b - 0 0";
+        let nodes = search_with_options(kings, &depth(1), &options).1;
+        assert!((4..=4 * options.threads as u64).contains(&nodes));
+
+        // The node limit applies to the nodes of all the threads.
+        let limits = SearchLimits {
+            nodes: Some(5000),
+            ..Default::default()
+        };
+        let startpos = "bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0";
+        let nodes = search_with_options(startpos, &limits, &options).1;
+        assert!((5000..5000 + options.threads as u64).contains(&nodes));
+    }
+
+    #[test]
+    fn mate_search() {
+        let solve = |mfen: &str, nodes| {
+            let mut position = Position::from_str(mfen).unwrap();
+            let limits = MateLimits {
+                nodes: Some(nodes),
+                ..Default::default()
+            };
+            let result = mate::mate_search(&mut position, &limits, &Signals::default());
+            assert!(equals(&position, &Position::from_str(mfen).unwrap()));
+            result
+        };
+        let mate_1 = "k7/8/G7/1N6/8/8/8/7K b - 0 0";
+        let position = Position::from_str(mate_1).unwrap();
+        assert_eq!(
+            solve(mate_1, 1000),
+            MateResult::Mate(vec![position.read_move("A6A7".to_string()).unwrap()])
+        );
+
+        let mate_3 = "1k6/8/2BP4/3L4/8/8/8/4K3 b G 0 0";
+        let MateResult::Mate(moves) = solve(mate_3, 100000) else {
+            panic!("no mate found");
+        };
+        assert_eq!(moves.len(), 5);
+        let mut position = Position::from_str(mate_3).unwrap();
+        for (i, &mv) in moves.iter().enumerate() {
+            let mut list = MoveList::new();
+            list.generate(&position, GenType::Legal);
+            assert!((0..list.size).any(|j| list.at(j).mv == mv));
+            position.do_move(mv, None);
+            // The attacker checks at every move.
+            assert_eq!(position.checkers() != 0, i % 2 == 0);
+        }
+        assert_eq!(
+            position.game_result(None),
+            GameResult::Win(Side::Black, WinReason::Checkmate)
+        );
+
+        // No checking move.
+        assert_eq!(
+            solve("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0", 1000),
+            MateResult::NoMate
+        );
+        assert_eq!(solve(mate_3, 1), MateResult::Unknown);
+    }
+
+    struct ManualClock(Cell<u64>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn time_manager() {
+        let clock = ManualClock(Cell::new(0));
+        let limits = |time: Option<u64>, byoyomi| SearchLimits {
+            time: [time, time],
+            byoyomi,
+            ..Default::default()
+        };
+
+        let time = TimeManager::new(&SearchLimits::movetime(1000), Side::Black, 0, 100, &clock);
+        assert_eq!(
+            (time.soft_limit(), time.hard_limit()),
+            (Some(900), Some(900))
+        );
+        let time = TimeManager::new(&SearchLimits::default(), Side::Black, 0, 100, &clock);
+        assert_eq!((time.soft_limit(), time.hard_limit()), (None, None));
+        let time = TimeManager::new(&limits(None, 10000), Side::Black, 0, 100, &clock);
+        assert_eq!(
+            (time.soft_limit(), time.hard_limit()),
+            (Some(9900), Some(9900))
+        );
+        // Later in the game, the remaining time is divided into fewer moves.
+        let time = TimeManager::new(&limits(Some(60000), 0), Side::Black, 0, 100, &clock);
+        assert_eq!(
+            (time.soft_limit(), time.hard_limit()),
+            (Some(1200), Some(4800))
+        );
+        let late = TimeManager::new(&limits(Some(60000), 0), Side::Black, 100, 100, &clock);
+        assert!(late.soft_limit().unwrap() > time.soft_limit().unwrap());
+
+        // A stable best move stops the search earlier.
+        let mut time = TimeManager::new(&limits(Some(60000), 0), Side::Black, 0, 100, &clock);
+        clock.0.set(1000);
+        assert!(!time.iteration_done(1, 0));
+        assert!(!time.iteration_done(1, 0));
+        assert!(time.iteration_done(1, 0));
+
+        // A dropping score extends the search.
+        clock.0.set(0);
+        let mut time = TimeManager::new(&limits(Some(60000), 0), Side::Black, 0, 100, &clock);
+        assert!(!time.iteration_done(1, 100));
+        clock.0.set(1500);
+        assert!(!time.iteration_done(1, -100));
+        assert!(time.iteration_done(1, -100));
+        assert!(!time.hard_passed());
+        clock.0.set(4800);
+        assert!(time.hard_passed());
+
+        // A movetime search is not cut short by a stable best move.
+        clock.0.set(0);
+        let mut time = TimeManager::new(&SearchLimits::movetime(1000), Side::Black, 0, 100, &clock);
+        clock.0.set(800);
+        for _ in 0..10 {
+            assert!(!time.iteration_done(1, 0));
+        }
+        clock.0.set(900);
+        assert!(time.iteration_done(1, 0));
+        assert!(time.hard_passed());
+
+        // The clock starts again on ponderhit.
+        time.restart();
+        assert_eq!(time.elapsed(), 0);
+        assert!(!time.hard_passed());
+    }
+
+    #[test]
+    fn foreign_moves() {
+        // Moves of other positions, such as killer moves, must be rejected unless legal.
+        let mut rng = Xoshiro256StarStar::seed_from_u64(7);
+        for _ in 0..50 {
+            let mut position =
+                Position::from_str("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0").unwrap();
+            let mut seen = Vec::new();
+            for _ in 0..200 {
+                let mut list = MoveList::new();
+                list.generate(&position, GenType::Legal);
+                if list.size == 0 {
+                    break;
+                }
+                let legal: Vec<_> = (0..list.size).map(|i| list.at(i).mv).collect();
+                for &mv in &seen {
+                    if !is_demise(mv) && position.is_pseudo_legal(mv) && position.is_legal(mv) {
+                        assert!(
+                            legal.contains(&mv),
+                            "{} {}",
+                            position,
+                            move_to_mfen(mv, position.side)
+                        );
+                    }
+                }
+                seen.extend(legal.iter().copied());
+                let mv = legal[rng.gen_range(0..legal.len())];
+                position.do_move(mv, None);
+            }
+        }
+    }
+
+    #[test]
+    fn see() {
+        let see = |mfen: &str, mv: &str, threshold| {
+            let position = Position::from_str(mfen).unwrap();
+            let mv = position.read_move(mv.to_string()).unwrap();
+            position.see(mv, threshold)
+        };
+        // Undefended knight.
+        assert!(see("k7/8/8/8/3n4/3G4/8/7K b - 0 0", "D3D4", 800));
+        assert!(!see("k7/8/8/8/3n4/3G4/8/7K b - 0 0", "D3D4", 801));
+        // Light defended by a general.
+        assert!(!see("k7/8/8/3g4/3l4/3G4/8/7K b - 0 0", "D3D4", 0));
+        assert!(see("k7/8/8/3g4/3l4/3G4/8/7K b - 0 0", "D3D4", -600));
+        // Heavy recapturing by the two-step move unless the middle square is occupied.
+        assert!(!see("k7/8/3h4/8/3l4/3G4/8/7K b - 0 0", "D3D4", 0));
+        assert!(see("k7/8/3h4/3L4/3l4/3G4/8/7K b - 0 0", "D3D4", 0));
+        // The arrow left by a shoot can be captured.
+        assert!(see("k7/8/8/n7/8/8/8/B6K b - 0 0", "A1A5S", 800));
+        assert!(see("k7/8/g7/n7/8/8/8/B6K b - 0 0", "A1A5S", 0));
+        assert!(!see("k7/8/g7/n7/8/8/8/B6K b - 0 0", "A1A5S", 1));
+    }
+
+    /// Compares the checking moves with the legal moves giving check down to `depth`.
+    fn verify_checks(position: &mut Position, depth: usize) {
+        let mut list = MoveList::new();
+        list.generate(position, GenType::Legal);
+        let legal: Vec<_> = (0..list.size).map(|i| list.at(i).mv).collect();
+        if position.checkers() == 0 {
+            let mut expected: Vec<_> = legal
+                .iter()
+                .copied()
+                .filter(|&mv| {
+                    position.do_move(mv, None);
+                    let check = position.checkers() != 0;
+                    position.undo_move(mv);
+                    check
+                })
+                .collect();
+            expected.sort();
+            for gen in [GenType::Checks, GenType::QuietChecks] {
+                let mut list = MoveList::new();
+                list.generate(position, gen);
+                let mut checks: Vec<_> = (0..list.size)
+                    .map(|i| list.at(i).mv)
+                    .filter(|&mv| position.is_legal(mv))
+                    .collect();
+                checks.sort();
+                let expected: Vec<_> = expected
+                    .iter()
+                    .copied()
+                    .filter(|&mv| gen == GenType::Checks || get_capture(mv) == PieceType::None)
+                    .collect();
+                assert_eq!(checks, expected, "{:?} {}", gen, position);
+            }
+        }
+        if depth > 1 {
+            for mv in legal {
+                position.do_move(mv, None);
+                verify_checks(position, depth - 1);
+                position.undo_move(mv);
+            }
+        }
+    }
+
+    #[test]
+    fn check_moves() {
+        for mfen in [
+            "bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0",
+            // Discovered checks by the archer and the heavy, and a return opening the line.
+            "3k4/8/3L4/8/3C1H2/2R5/4R3/B3K3 b LHGNRA 0 0",
+            "4k3/8/4p3/8/1b6/8/3n4/4K3 w lhgnra 0 0",
+        ] {
+            let mut position = Position::from_str(mfen).unwrap();
+            verify_checks(&mut position, 3);
+        }
+        // Positions with pieces in hand and arrows on the board.
+        let mut rng = Xoshiro256StarStar::seed_from_u64(17);
+        for _ in 0..20 {
+            let mut position =
+                Position::from_str("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0").unwrap();
+            for _ in 0..300 {
+                verify_checks(&mut position, 1);
+                let mut list = MoveList::new();
+                list.generate(&position, GenType::Legal);
+                if list.size == 0 {
+                    break;
+                }
+                let mv = list.at(rng.gen_range(0..list.size)).mv;
+                position.do_move(mv, None);
+            }
+        }
+    }
+
+    #[test]
+    fn gives_check() {
+        // `gives_check` must agree with the checkers after the move for every kind of move.
+        let mut rng = Xoshiro256StarStar::seed_from_u64(18);
+        let mut kinds = [0; 6];
+        let mfens: Vec<_> = include_str!("../perft.txt")
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split(';').next().unwrap())
+            .collect();
+        for mfen in mfens {
+            for _ in 0..10 {
+                let mut position = Position::from_str(mfen).unwrap();
+                for _ in 0..200 {
+                    let mut list = MoveList::new();
+                    list.generate(&position, GenType::Legal);
+                    if list.size == 0 {
+                        break;
+                    }
+                    let mut moves: Vec<_> = (0..list.size).map(|i| list.at(i).mv).collect();
+                    if position.is_legal_move(MOVE_DEMISE) {
+                        moves.push(MOVE_DEMISE);
+                    }
+                    let side = position.side;
+                    for &mv in &moves {
+                        let gives_check = position.gives_check(mv);
+                        position.do_move(mv, None);
+                        if mv != MOVE_DEMISE {
+                            assert_eq!(position.checkers(), position.calculate_checkers());
+                        }
+                        // Demise alone does not pass the turn, so the opponent is set to move.
+                        let next = position.side;
+                        position.side = !side;
+                        let checked = position.calculate_checkers() != 0;
+                        position.side = next;
+                        assert_eq!(
+                            gives_check,
+                            checked,
+                            "{} {}",
+                            position,
+                            move_to_mfen(mv, side)
+                        );
+                        position.undo_move(mv);
+
+                        let kind = if mv == MOVE_DEMISE || is_demise(mv) {
+                            5
+                        } else {
+                            get_move_type(mv) as usize
+                        };
+                        kinds[kind] += 1;
+                    }
+                    let mv = list.at(rng.gen_range(0..list.size)).mv;
+                    position.do_move(mv, None);
+                }
+            }
+        }
+        // Every kind of move is checked.
+        assert!(kinds.iter().all(|&n| n > 0), "{:?}", kinds);
+    }
+
+    #[test]
+    fn mfen_hand() {
+        // Every piece in hand is read, with or without a count.
+        let position = Position::from_str("3k4/8/3L4/8/3C1H2/2R5/4R3/B3K3 b LHGNRA 0 0").unwrap();
+        for pt in [
+            PieceType::Light,
+            PieceType::Heavy,
+            PieceType::General,
+            PieceType::Knight,
+            PieceType::Arrow,
+            PieceType::Archer0,
+        ] {
+            assert_eq!(position.count_hand(Side::Black, pt), 1);
+            assert_eq!(position.count_hand(Side::White, pt), 0);
+        }
+
+        let position =
+            Position::from_str("b1ln3g/G3l1pa/5gl1/lh3knh/H3RH1h/R1G2HKR/A3L3/Hn3A2 w LNl2h 2 1")
+                .unwrap();
+        assert_eq!(position.count_hand(Side::Black, PieceType::Light), 1);
+        assert_eq!(position.count_hand(Side::Black, PieceType::Knight), 1);
+        assert_eq!(position.count_hand(Side::White, PieceType::Light), 2);
+        assert_eq!(position.count_hand(Side::White, PieceType::Heavy), 1);
+        let reparsed = Position::from_str(&position.to_string()).unwrap();
+        assert_eq!(reparsed.hands, position.hands);
+    }
+
+    /// Counts the leaf nodes by playing every pseudo-legal move and dropping those leaving the
+    /// crown in check, without the generator of evasions and `is_legal`.
+    fn naive_perft(position: &mut Position, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let side = position.side;
+        let mut list = MoveList::new();
+        list.generate(position, GenType::All);
+        let mut moves: Vec<_> = (0..list.size).map(|i| list.at(i).mv).collect();
+        if position.checkers() != 0 {
+            // Demise is allowed only in check and when the heir is safe.
+            let heir = match position.demise[side as usize] {
+                0 => position.piece_list[side as usize][PieceType::Prince as usize][0],
+                1 => position.piece_list[side as usize][PieceType::King as usize][0],
+                _ => Square::NONE,
+            };
+            if heir != Square::NONE && !position.is_attacked(heir, side) {
+                for i in 0..moves.len() {
+                    moves.push(moves[i] | MOVE_DEMISE);
+                }
+            }
+        }
+        moves.sort_unstable();
+        moves.dedup();
+
+        let mut nodes = 0;
+        for mv in moves {
+            position.do_move(mv, None);
+            position.side = side;
+            let legal = position.calculate_checkers() == 0;
+            position.side = !side;
+            if legal {
+                nodes += naive_perft(position, depth - 1);
+            }
+            position.undo_move(mv);
+        }
+        nodes
+    }
+
+    #[test]
+    fn perft_naive() {
+        // The general is between its own archer and its king, which does not pin it.
+        let position =
+            Position::from_str("b1gkpgnb/lRhhhhll/n7/8/8/6L1/LLHHHH1L/BNGPKGNA w L 0 0").unwrap();
+        let mv = make_move_normal(PieceType::Arrow, Square::C8, Square::B7);
+        assert!(position.is_pseudo_legal(mv) && position.is_legal(mv));
+
+        for line in include_str!("../perft.txt").lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split(';');
+            let mut position = Position::from_str(fields.next().unwrap()).unwrap();
+            for field in fields {
+                let (depth, nodes) = field.trim_start_matches('D').split_once(' ').unwrap();
+                let depth: usize = depth.parse().unwrap();
+                let nodes: u64 = nodes.parse().unwrap();
+                assert_eq!(
+                    naive_perft(&mut position, depth),
+                    nodes,
+                    "{} {}",
+                    line,
+                    depth
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn evasion() {
+        // The crown capturing its only checker is generated once.
+        let position = Position::from_str("4k3/8/8/8/8/8/4l3/4K3 b - 0 0").unwrap();
+        let mut list = MoveList::new();
+        list.generate(&position, GenType::Legal);
+        let mut moves: Vec<_> = (0..list.size).map(|i| list.at(i).mv).collect();
+        moves.sort_unstable();
+        moves.dedup();
+        assert_eq!(moves.len(), list.size);
+        assert_eq!(list.size, 3);
+    }
+
+    #[test]
+    fn perft_suite() {
+        for line in include_str!("../perft.txt").lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split(';');
+            let mut position = Position::from_str(fields.next().unwrap()).unwrap();
+            for field in fields {
+                let (depth, nodes) = field.trim_start_matches('D').split_once(' ').unwrap();
+                let depth: usize = depth.parse().unwrap();
+                let nodes: u64 = nodes.parse().unwrap();
+                assert_eq!(
+                    perft::perft(&mut position, depth),
+                    nodes,
+                    "{} {}",
+                    line,
+                    depth
+                );
+                if depth <= 2 {
+                    let divide = perft::perft_divide(&mut position, depth);
+                    assert_eq!(divide.iter().map(|d| d.1).sum::<u64>(), nodes);
+                    assert_eq!(perft::perft_stats(&mut position, depth).nodes, nodes);
+                }
+            }
+        }
+
+        let mut position =
+            Position::from_str("b1g1pgna/llhkh1rl/n1Rh2l1/2H5/5hL1/LL5L/3HHK2/BNGP1GNA b h 0 0")
+                .unwrap();
+        assert_eq!(
+            perft::perft_stats(&mut position, 3),
+            perft::PerftStats {
+                nodes: 44732,
+                captures: 1917,
+                drops: 1039,
+                shoots: 7866,
+                returns: 1294,
+                supplies: 88,
+                demises: 204,
+                checks: 158,
+            }
+        );
+    }
+
+    /// Plays the move string if it is parsed as a legal move, checking the key.
+    fn try_move(position: &mut Position, mv: &str) {
+        let Ok(mv) = position.read_move(mv.to_string()) else {
+            return;
+        };
+        let mfen = position.to_string();
+        position.do_move(mv, None);
+        assert_eq!(position.key(), position.compute_key(), "{}", mfen);
+        position.undo_move(mv);
+        assert_eq!(position.to_string(), mfen);
+    }
+
+    #[test]
+    fn parse_random_strings() {
+        // Mutations of valid MFENs and random move strings must not panic.
+        const CHARS: &[u8] = b"0123456789/LHKPGNRABClhkpgnrabc-bw SDE9 \xff";
+        let mut rng = Xoshiro256StarStar::seed_from_u64(20);
+        let mfens: Vec<_> = include_str!("../perft.txt")
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split(';').next().unwrap().to_string())
+            .collect();
+        for _ in 0..20000 {
+            let mut mfen = mfens[rng.gen_range(0..mfens.len())].as_bytes().to_vec();
+            for _ in 0..rng.gen_range(1..4) {
+                let i = rng.gen_range(0..mfen.len());
+                let c = CHARS[rng.gen_range(0..CHARS.len())];
+                match rng.gen_range(0..3) {
+                    0 => mfen[i] = c,
+                    1 => mfen.insert(i, c),
+                    _ => {
+                        mfen.remove(i);
+                    }
+                }
+            }
+            let mfen = String::from_utf8_lossy(&mfen).to_string();
+            let Ok(mut position) = Position::from_str(&mfen) else {
+                continue;
+            };
+            // Parsed positions are written back in the same form.
+            let written = position.to_string();
+            let reparsed = Position::from_str(&written).unwrap();
+            assert_eq!(reparsed.to_string(), written, "{}", mfen);
+            assert_eq!(reparsed.key(), position.key(), "{}", mfen);
+
+            for _ in 0..20 {
+                let len = rng.gen_range(1..7);
+                let mv: String = (0..len)
+                    .map(|_| CHARS[rng.gen_range(0..CHARS.len())] as char)
+                    .collect();
+                try_move(&mut position, &mv);
+            }
+            let mut list = MoveList::new();
+            list.generate(&position, GenType::Legal);
+            for i in 0..list.size {
+                let mv = move_to_mfen(list.at(i).mv, position.side);
+                try_move(&mut position, &mv);
+            }
+        }
+    }
+
+    #[test]
+    fn load_errors() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("alex-book-{}.txt", std::process::id()));
+        let startpos = "bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0";
+        fs::write(&path, format!("# book\n{} C2C4 C2C5\n", startpos)).unwrap();
+        let result = Book::load(&path);
+        assert!(matches!(
+            result,
+            Err(BookError::InvalidMove(2, MoveError::Illegal(ref mv))) if mv == "C2C5"
+        ));
+        fs::write(
+            &path,
+            "bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB x - 0 0\n",
+        )
+        .unwrap();
+        let result = Book::load(&path);
+        assert!(matches!(
+            result,
+            Err(BookError::InvalidMfen(1, MfenError::InvalidSide(ref side))) if side == "x"
+        ));
+        fs::write(&path, "demise 99999\n").unwrap();
+        let result = EvalParams::load(&path);
+        assert!(matches!(result, Err(EvalParamsError::Syntax(1, _))));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(Book::load(&path), Err(BookError::Io(_))));
+        assert!(matches!(
+            EvalParams::load(&path),
+            Err(EvalParamsError::Io(_))
+        ));
+
+        assert_eq!(
+            "draw foo".parse::<GameResult>(),
+            Err(GameResultError::InvalidReason("foo".to_string()))
+        );
+        assert_eq!(
+            "MFEN: x\n".parse::<Kifu>(),
+            Err(KifuError::InvalidMfen(
+                "x".to_string(),
+                MfenError::FieldCount(1)
+            ))
+        );
+    }
+
+    #[test]
+    fn kifu() {
+        let mut kifu = Kifu {
+            black: Some("alice".to_string()),
+            date: Some("2024-01-31".to_string()),
+            ..Default::default()
+        };
+        let mut position = kifu.position().unwrap();
+        let mut rng = Xoshiro256StarStar::seed_from_u64(21);
+        for i in 0..60 {
+            let mut list = MoveList::new();
+            list.generate(&position, GenType::Legal);
+            if list.size == 0 {
+                break;
+            }
+            let mv = list.at(rng.gen_range(0..list.size)).mv;
+            position.do_move(mv, None);
+            let mut m = KifuMove::new(mv);
+            m.time = Some(i * 100);
+            m.eval = (i % 2 == 0).then_some(i as i16 - 30);
+            m.comment = (i % 7 == 0).then(|| format!("comment {}\nsecond line", i));
+            kifu.moves.push(m);
+        }
+        kifu.result = position.game_result(None);
+
+        let text = kifu.to_string();
+        let read: Kifu = text.parse().unwrap();
+        assert_eq!(read.moves.len(), kifu.moves.len());
+        assert_eq!(read.to_string(), text);
+        assert_eq!(read.position().unwrap().key(), position.key());
+
+        // The ply of an illegal move is reported.
+        let text =
+            "MFEN: bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0\n\n1 C2C3\n2 F7F6\n3 C3D5\n";
+        assert_eq!(
+            text.parse::<Kifu>(),
+            Err(KifuError::IllegalMove(
+                3,
+                MoveError::Illegal("C3D5".to_string())
+            ))
+        );
+        assert_eq!(
+            "Result: unknown\n".parse::<Kifu>(),
+            Err(KifuError::Syntax(1, "Result: unknown".to_string()))
+        );
+        assert_eq!(
+            "\n1 C2C3 time\n".parse::<Kifu>(),
+            Err(KifuError::Syntax(2, "1 C2C3 time".to_string()))
+        );
+    }
+
+    #[test]
+    fn pgn() {
+        let mut kifu = Kifu {
+            white: Some("bob \"the\" builder".to_string()),
+            date: Some("2024-01-31".to_string()),
+            ..Default::default()
+        };
+        let mut position = kifu.position().unwrap();
+        let mut rng = Xoshiro256StarStar::seed_from_u64(22);
+        let mut notations = Vec::new();
+        for i in 0..80 {
+            let mut list = MoveList::new();
+            list.generate(&position, GenType::Legal);
+            if list.size == 0 {
+                break;
+            }
+            let mv = list.at(rng.gen_range(0..list.size)).mv;
+            notations.push(position.notation(mv));
+            position.do_move(mv, None);
+            let mut m = KifuMove::new(mv);
+            m.time = (i % 3 != 0).then_some(i * 1234);
+            m.eval = (i % 2 == 0).then_some(i as i16 * 7 - 300);
+            m.comment = (i % 5 == 0).then(|| format!("comment {{{}}}", i));
+            kifu.moves.push(m);
+        }
+        kifu.result = position.game_result(None);
+
+        let pgn = kifu.to_pgn().unwrap();
+        assert!(pgn.contains("[MFEN \"bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0\"]"));
+        // Moves are written in the same notation as `Position::notation`.
+        assert!(pgn.contains(&format!("1. {} ", notations[0])));
+        let read = Kifu::from_pgn(&pgn).unwrap();
+        assert_eq!(read.white, kifu.white);
+        assert_eq!(read.date, kifu.date);
+        assert_eq!(read.result, kifu.result);
+        for (a, b) in read.moves.iter().zip(&kifu.moves) {
+            assert_eq!((a.mv, a.time, a.eval), (b.mv, b.time, b.eval));
+        }
+        assert_eq!(read.moves.len(), kifu.moves.len());
+        assert_eq!(read.to_pgn().unwrap(), pgn);
+
+        // The piece letter must match the board.
+        let text = "[Result \"*\"]\n\n1. Lc3 *\n";
+        assert_eq!(
+            Kifu::from_pgn(text),
+            Err(KifuError::IllegalMove(
+                1,
+                MoveError::Illegal("Lc3".to_string())
+            ))
+        );
+        let text = "[Demise \"1 0\"]\n\n1. Hc3 Hf6\n";
+        assert_eq!(
+            Kifu::from_pgn(text),
+            Err(KifuError::Syntax(1, "[Demise \"1 0\"]".to_string()))
+        );
+    }
+
+    #[test]
+    fn notation() {
+        let position = Position::from_str(STARTPOS).unwrap();
+        let read = |position: &Position, mfen: &str| {
+            let mv = position.read_move(mfen.to_string()).unwrap();
+            position.notation(mv)
+        };
+        assert_eq!(read(&position, "C2C4"), "Hc4");
+        assert_eq!(read(&position, "B1C3"), "Nc3");
+        assert_eq!(
+            position.read_move("C2C5".to_string()),
+            Err(MoveError::Illegal("C2C5".to_string()))
+        );
+        assert_eq!(
+            position.read_move("A7A6".to_string()),
+            Err(MoveError::NoSuchPiece("A7A6".to_string()))
+        );
+        assert_eq!(
+            position.read_notation("Lc3"),
+            Err(MoveError::Illegal("Lc3".to_string()))
+        );
+        assert_eq!(
+            position.read_notation("hc4"),
+            Err(MoveError::InvalidNotation("hc4".to_string()))
+        );
+        assert_eq!(
+            position.read_notation("Hc4S"),
+            Err(MoveError::Illegal("Hc4S".to_string()))
+        );
+
+        let position = Position::from_str("k7/8/8/8/3n4/3G1G2/8/7K b R 0 0").unwrap();
+        assert_eq!(read(&position, "D3D4"), "Gxd4");
+        assert_eq!(read(&position, "D3E3"), "Gde3");
+        assert_eq!(read(&position, "F3E3"), "Gfe3");
+        assert_eq!(read(&position, "E5R"), "R*e5");
+        assert_eq!(
+            position.read_notation("Ge3"),
+            Err(MoveError::Ambiguous("Ge3".to_string()))
+        );
+        assert_eq!(
+            position.read_notation("Gf3e3"),
+            position.read_notation("Gfe3")
+        );
+        let position = Position::from_str("k7/8/8/8/3G4/8/3G4/7K b - 0 0").unwrap();
+        assert_eq!(read(&position, "D4D3"), "G4d3");
+
+        // Every legal move is written uniquely and read back.
+        let mut rng = Xoshiro256StarStar::seed_from_u64(23);
+        for _ in 0..4 {
+            let mut position = Position::from_str(STARTPOS).unwrap();
+            for _ in 0..100 {
+                let mut list = MoveList::new();
+                list.generate(&position, GenType::Legal);
+                if list.size == 0 {
+                    break;
+                }
+                let mut seen = HashSet::new();
+                for i in 0..list.size {
+                    let mv = list.at(i).mv;
+                    let notation = position.notation(mv);
+                    assert!(seen.insert(notation.clone()), "{}", notation);
+                    assert_eq!(position.read_notation(&notation), Ok(mv), "{}", notation);
+                    let mfen = move_to_mfen(mv, position.side);
+                    assert_eq!(position.read_move(mfen), Ok(mv));
+                }
+                position.do_move(list.at(rng.gen_range(0..list.size)).mv, None);
+            }
+        }
+    }
+
+    #[test]
+    fn read_move() {
+        let position = Position::from_str(STARTPOS).unwrap();
+        let read = |mv: &str| position.read_move(mv.to_string());
+        let error = |e: fn(String) -> MoveError, mv: &str| Err(e(mv.to_string()));
+        assert!(read("C2C4").is_ok());
+        assert_eq!(read("C2C9"), Err(MoveError::InvalidRank('9')));
+        assert_eq!(read("C3L"), error(MoveError::NoSuchPiece, "C3L"));
+        assert_eq!(read("C3K"), error(MoveError::NoSuchPiece, "C3K"));
+        assert_eq!(read("C3C4"), error(MoveError::NoSuchPiece, "C3C4"));
+        assert_eq!(read("C7C6"), error(MoveError::NoSuchPiece, "C7C6"));
+        assert_eq!(read("C2C5"), error(MoveError::Illegal, "C2C5"));
+        // The crown may pass to the prince without moving.
+        assert_eq!(read("D"), Ok(MOVE_DEMISE));
+
+        let position =
+            Position::from_str("bng2gnb/llhkh1ll/3h1hp1/8/2H1HH2/NL1H4/L5LL/ARGPKGNB w - 0 0")
+                .unwrap();
+        assert_eq!(
+            position.read_move("D7C6".to_string()),
+            error(MoveError::CrownAttacked, "D7C6")
+        );
+        assert!(position.read_move("D7D8".to_string()).is_ok());
+    }
+
+    #[test]
+    fn mfen_error() {
+        let error = |mfen: &str| Position::from_str(mfen).err();
+        assert_eq!(
+            error("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0"),
+            Some(MfenError::FieldCount(4))
+        );
+        assert_eq!(
+            error("bngkpgnb/llhhhhll/8/8/8/3x4/LLHHHHLL/BNGPKGNB b - 0 0"),
+            Some(MfenError::InvalidChar('x', 3))
+        );
+        assert_eq!(
+            error("bngkpgnb/llhhhhll/8/8/7/8/LLHHHHLL/BNGPKGNB b - 0 0"),
+            Some(MfenError::InvalidRank(4))
+        );
+        assert_eq!(
+            error("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB x - 0 0"),
+            Some(MfenError::InvalidSide("x".to_string()))
+        );
+        assert_eq!(
+            error("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b K 0 0"),
+            Some(MfenError::InvalidHandPiece('K'))
+        );
+        assert_eq!(
+            error("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b L1 0 0"),
+            Some(MfenError::InvalidHandCount('L', 1))
+        );
+        assert_eq!(
+            error("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 3"),
+            Some(MfenError::InvalidDemise(Side::White, "3".to_string()))
+        );
+        assert_eq!(
+            error("bng1pgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0"),
+            Some(MfenError::NoCrown(Side::White))
+        );
+        assert_eq!(
+            error("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b Z 0 0")
+                .unwrap()
+                .to_string(),
+            "invalid piece: Z"
+        );
+    }
 }
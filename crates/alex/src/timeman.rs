@@ -0,0 +1,160 @@
+use crate::{
+    search::SearchLimits,
+    types::{Move, Side, Value, MOVE_NONE},
+};
+
+/// Source of the current time in milliseconds.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+/// Clock of the system.
+#[derive(Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        chrono::Local::now().timestamp_millis() as u64
+    }
+}
+
+impl<C: Clock> Clock for &C {
+    fn now(&self) -> u64 {
+        (*self).now()
+    }
+}
+
+/// Moves expected to be played in a game.
+const MOVE_HORIZON: u64 = 50;
+/// Minimum count of moves the remaining time is divided into.
+const MIN_MOVES_TO_GO: u64 = 20;
+/// Maximum ratio of the hard limit to the soft limit.
+const MAX_RATIO: u64 = 4;
+/// Drop of the score which doubles the time.
+const SCORE_DROP_MAX: i32 = 200;
+
+/// Allocates the time of a search.
+pub struct TimeManager<C: Clock> {
+    clock: C,
+    start: u64,
+    /// Time after which no new iteration is started.
+    soft: Option<u64>,
+    /// Time at which the search stops at once.
+    hard: Option<u64>,
+    /// The time is given by movetime, so it is not scaled.
+    fixed: bool,
+    best_move: Move,
+    /// Count of iterations in a row with the same best move.
+    stability: u32,
+    value: Option<Value>,
+}
+
+impl<C: Clock> TimeManager<C> {
+    /// Computes the limits for `side` to move at the ply `ply` of the game.
+    pub fn new(
+        limits: &SearchLimits,
+        side: Side,
+        ply: usize,
+        move_overhead: u64,
+        clock: C,
+    ) -> Self {
+        let (soft, hard) = allocate(limits, side, ply, move_overhead);
+        TimeManager {
+            start: clock.now(),
+            clock,
+            soft,
+            hard,
+            fixed: limits.movetime.is_some(),
+            best_move: MOVE_NONE,
+            stability: 0,
+            value: None,
+        }
+    }
+
+    /// Starts the clock again, such as on ponderhit.
+    pub fn restart(&mut self) {
+        self.start = self.clock.now();
+    }
+
+    /// Returns the elapsed time in milliseconds.
+    pub fn elapsed(&self) -> u64 {
+        self.clock.now().saturating_sub(self.start)
+    }
+
+    pub fn soft_limit(&self) -> Option<u64> {
+        self.soft
+    }
+
+    pub fn hard_limit(&self) -> Option<u64> {
+        self.hard
+    }
+
+    /// Returns true if the search must stop at once.
+    pub fn hard_passed(&self) -> bool {
+        self.hard.is_some_and(|hard| self.elapsed() >= hard)
+    }
+
+    /// Records the result of a completed iteration and returns true if the next one should not start.
+    /// The soft limit is shortened while the best move is stable and extended when the score drops.
+    pub fn iteration_done(&mut self, best_move: Move, value: Value) -> bool {
+        if best_move == self.best_move {
+            self.stability += 1;
+        } else {
+            self.best_move = best_move;
+            self.stability = 0;
+        }
+        let drop = self
+            .value
+            .map_or(0, |v| (v as i32 - value as i32).clamp(0, SCORE_DROP_MAX));
+        self.value = Some(value);
+
+        let Some(soft) = self.soft else {
+            return false;
+        };
+        if self.fixed {
+            return self.elapsed() >= soft;
+        }
+        // 1.0 for a new best move, down to 0.5 for a stable one.
+        let stability = 1.0 - 0.1 * self.stability.min(5) as f64;
+        let drop = 1.0 + drop as f64 / SCORE_DROP_MAX as f64;
+        let target = (soft as f64 * stability * drop) as u64;
+        let target = self.hard.map_or(target, |hard| target.min(hard));
+        self.elapsed() >= target
+    }
+}
+
+/// Returns the soft and hard limits in milliseconds.
+fn allocate(
+    limits: &SearchLimits,
+    side: Side,
+    ply: usize,
+    move_overhead: u64,
+) -> (Option<u64>, Option<u64>) {
+    if limits.infinite {
+        return (None, None);
+    }
+    if let Some(movetime) = limits.movetime {
+        let movetime = movetime.saturating_sub(move_overhead);
+        return (Some(movetime), Some(movetime));
+    }
+    let time = limits.time[side as usize];
+    if time.is_none() && limits.byoyomi == 0 {
+        return (None, None);
+    }
+    let time = time.unwrap_or(0);
+    let inc = limits.inc[side as usize];
+    let byoyomi = limits.byoyomi;
+
+    let moves_played = ply as u64 / 2;
+    let moves_to_go = MOVE_HORIZON
+        .saturating_sub(moves_played)
+        .max(MIN_MOVES_TO_GO);
+    // Keep a margin so that the clock does not run out.
+    let available = (time + byoyomi).saturating_sub(move_overhead);
+    let soft = (time / moves_to_go + inc + byoyomi).min(available);
+    let hard = (soft * MAX_RATIO)
+        .min(time / 4 + inc + byoyomi)
+        .min(available)
+        .max(soft);
+    (Some(soft), Some(hard))
+}
@@ -0,0 +1,229 @@
+use std::{
+    mem::size_of,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+};
+
+use crate::types::{Key, Move, Value, MOVE_NONE, VALUE_WIN_IN_MAX_PLY};
+
+/// Type of the bound of a value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u64)]
+pub enum Bound {
+    None,
+    /// The value is an upper bound (fail-low).
+    Upper,
+    /// The value is a lower bound (fail-high).
+    Lower,
+    /// The value is exact.
+    Exact,
+}
+
+/// Entry of the transposition table.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TTEntry {
+    pub mv: Move,
+    pub value: Value,
+    pub depth: usize,
+    pub bound: Bound,
+    pub generation: u8,
+}
+
+const DATA_MOVE: u64 = 0x1FFFFF;
+const DATA_MOVE_SHIFT: u64 = 0;
+const DATA_VALUE: u64 = 0xFFFF;
+const DATA_VALUE_SHIFT: u64 = 21;
+const DATA_DEPTH: u64 = 0xFF;
+const DATA_DEPTH_SHIFT: u64 = 37;
+const DATA_BOUND: u64 = 0b11;
+const DATA_BOUND_SHIFT: u64 = 45;
+const DATA_GENERATION: u64 = 0xFF;
+const DATA_GENERATION_SHIFT: u64 = 47;
+
+impl TTEntry {
+    fn pack(&self) -> u64 {
+        ((self.mv as u64 & DATA_MOVE) << DATA_MOVE_SHIFT)
+            | ((self.value as u16 as u64) << DATA_VALUE_SHIFT)
+            | ((self.depth.min(DATA_DEPTH as usize) as u64) << DATA_DEPTH_SHIFT)
+            | ((self.bound as u64) << DATA_BOUND_SHIFT)
+            | ((self.generation as u64) << DATA_GENERATION_SHIFT)
+    }
+
+    fn unpack(data: u64) -> Self {
+        let bound = match (data >> DATA_BOUND_SHIFT) & DATA_BOUND {
+            1 => Bound::Upper,
+            2 => Bound::Lower,
+            3 => Bound::Exact,
+            _ => Bound::None,
+        };
+        TTEntry {
+            mv: ((data >> DATA_MOVE_SHIFT) & DATA_MOVE) as Move,
+            value: ((data >> DATA_VALUE_SHIFT) & DATA_VALUE) as u16 as Value,
+            depth: ((data >> DATA_DEPTH_SHIFT) & DATA_DEPTH) as usize,
+            bound,
+            generation: ((data >> DATA_GENERATION_SHIFT) & DATA_GENERATION) as u8,
+        }
+    }
+}
+
+/// Slot of an entry.
+/// The key is stored xored with the data so that a torn write is detected as a miss.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Count of entries in a cluster.
+const CLUSTER_SIZE: usize = 4;
+
+#[derive(Default)]
+struct Cluster {
+    slots: [Slot; CLUSTER_SIZE],
+}
+
+/// Transposition table.
+pub struct TranspositionTable {
+    clusters: Vec<Cluster>,
+    generation: AtomicU8,
+}
+
+/// Default size of the transposition table in megabytes.
+pub const DEFAULT_HASH_SIZE: usize = 16;
+
+impl TranspositionTable {
+    /// Creates a transposition table of `mb` megabytes.
+    pub fn new(mb: usize) -> Self {
+        let mut tt = TranspositionTable {
+            clusters: Vec::new(),
+            generation: AtomicU8::new(0),
+        };
+        tt.resize(mb);
+        tt
+    }
+
+    /// Resizes the table to `mb` megabytes and clears it.
+    pub fn resize(&mut self, mb: usize) {
+        let count = (mb * 1024 * 1024 / size_of::<Cluster>()).max(1);
+        self.clusters = Vec::new();
+        self.clusters.resize_with(count, Cluster::default);
+    }
+
+    pub fn clear(&self) {
+        for cluster in &self.clusters {
+            for slot in &cluster.slots {
+                slot.key.store(0, Ordering::Relaxed);
+                slot.data.store(0, Ordering::Relaxed);
+            }
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Advances the generation. Called at the start of each search.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    fn cluster(&self, key: Key) -> &Cluster {
+        let index = ((key as u128 * self.clusters.len() as u128) >> 64) as usize;
+        &self.clusters[index]
+    }
+
+    pub fn probe(&self, key: Key) -> Option<TTEntry> {
+        for slot in &self.cluster(key).slots {
+            let data = slot.data.load(Ordering::Relaxed);
+            if data != 0 && slot.key.load(Ordering::Relaxed) ^ data == key {
+                return Some(TTEntry::unpack(data));
+            }
+        }
+        None
+    }
+
+    pub fn store(&self, key: Key, mv: Move, value: Value, depth: usize, bound: Bound) {
+        let generation = self.generation();
+        let cluster = self.cluster(key);
+        let mut replace = &cluster.slots[0];
+        let mut replace_score = i32::MAX;
+        for slot in &cluster.slots {
+            let data = slot.data.load(Ordering::Relaxed);
+            if data == 0 || slot.key.load(Ordering::Relaxed) ^ data == key {
+                replace = slot;
+                break;
+            }
+            // Prefer to replace shallow and old entries.
+            let entry = TTEntry::unpack(data);
+            let age = generation.wrapping_sub(entry.generation) as i32;
+            let score = entry.depth as i32 - 8 * age;
+            if score < replace_score {
+                replace = slot;
+                replace_score = score;
+            }
+        }
+
+        let old = replace.data.load(Ordering::Relaxed);
+        let mut mv = mv;
+        if replace.key.load(Ordering::Relaxed) ^ old == key {
+            let entry = TTEntry::unpack(old);
+            // Keep the deeper entry of the same search.
+            if bound != Bound::Exact && entry.generation == generation && entry.depth > depth + 2 {
+                return;
+            }
+            // Keep the old move if we do not have a new one.
+            if mv == MOVE_NONE {
+                mv = entry.mv;
+            }
+        }
+
+        let data = TTEntry {
+            mv,
+            value,
+            depth,
+            bound,
+            generation,
+        }
+        .pack();
+        replace.key.store(key ^ data, Ordering::Relaxed);
+        replace.data.store(data, Ordering::Relaxed);
+    }
+
+    /// Returns the permille of the table filled by the current search.
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation();
+        let count = self.clusters.len().min(1000 / CLUSTER_SIZE);
+        let mut used = 0;
+        for cluster in &self.clusters[..count] {
+            for slot in &cluster.slots {
+                let data = slot.data.load(Ordering::Relaxed);
+                if data != 0 && TTEntry::unpack(data).generation == generation {
+                    used += 1;
+                }
+            }
+        }
+        used * 1000 / (count * CLUSTER_SIZE)
+    }
+}
+
+/// Converts a win/loss value from "plies from the root" to "plies from the node".
+pub fn value_to_tt(value: Value, ply: usize) -> Value {
+    if value >= VALUE_WIN_IN_MAX_PLY {
+        value + ply as Value
+    } else if value <= -VALUE_WIN_IN_MAX_PLY {
+        value - ply as Value
+    } else {
+        value
+    }
+}
+
+/// Inverse of `value_to_tt`.
+pub fn value_from_tt(value: Value, ply: usize) -> Value {
+    if value >= VALUE_WIN_IN_MAX_PLY {
+        value - ply as Value
+    } else if value <= -VALUE_WIN_IN_MAX_PLY {
+        value + ply as Value
+    } else {
+        value
+    }
+}
@@ -38,28 +38,68 @@ impl fmt::Display for Square {
     }
 }
 
-pub fn read_file(c: u8) -> Result<usize, String> {
-    let a = 'A' as u8;
+/// Error of reading a move.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MoveError {
+    /// The move is not 3, 4 or 5 characters long without the demise.
+    InvalidLength(String),
+    InvalidFile(char),
+    InvalidRank(char),
+    /// The fifth character is not `S`.
+    InvalidSuffix(char),
+    InvalidPiece(char),
+    InvalidNotation(String),
+    /// The side to move has no piece to move from the square or to drop.
+    NoSuchPiece(String),
+    /// The piece cannot move so.
+    Illegal(String),
+    /// The move leaves the crown in check.
+    CrownAttacked(String),
+    /// The notation matches several legal moves.
+    Ambiguous(String),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::InvalidLength(mv) => write!(f, "invalid length of move: {}", mv),
+            MoveError::InvalidFile(c) => write!(f, "invalid file: {}", c),
+            MoveError::InvalidRank(c) => write!(f, "invalid rank: {}", c),
+            MoveError::InvalidSuffix(c) => write!(f, "invalid end of move: {}", c),
+            MoveError::InvalidPiece(c) => write!(f, "invalid piece: {}", c),
+            MoveError::InvalidNotation(mv) => write!(f, "invalid notation: {}", mv),
+            MoveError::NoSuchPiece(mv) => write!(f, "no piece to move: {}", mv),
+            MoveError::Illegal(mv) => write!(f, "illegal move: {}", mv),
+            MoveError::CrownAttacked(mv) => write!(f, "move leaves the crown attacked: {}", mv),
+            MoveError::Ambiguous(mv) => write!(f, "ambiguous move: {}", mv),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+pub fn read_file(c: u8) -> Result<usize, MoveError> {
+    let a = b'A';
     if c < a {
-        return Err("Invalid Character.".to_string());
+        return Err(MoveError::InvalidFile(c as char));
     }
     let x = (c - a) as usize;
     if x >= RANK_NB {
-        return Err("Invalid Character.".to_string());
+        return Err(MoveError::InvalidFile(c as char));
     }
-    return Ok(x);
+    Ok(x)
 }
 
-pub fn read_rank(c: u8) -> Result<usize, String> {
-    let a = '1' as u8;
+pub fn read_rank(c: u8) -> Result<usize, MoveError> {
+    let a = b'1';
     if c < a {
-        return Err("Invalid Character.".to_string());
+        return Err(MoveError::InvalidRank(c as char));
     }
     let y = (c - a) as usize;
     if y >= RANK_NB {
-        return Err("Invalid Character.".to_string());
+        return Err(MoveError::InvalidRank(c as char));
     }
-    return Ok(y);
+    Ok(y)
 }
 
 /// Type of the piece.
@@ -258,6 +298,9 @@ pub fn to_hand(pt: PieceType) -> Hand {
 /// Bitboard.
 pub type Bitboard = u64;
 
+/// Hash key of a position.
+pub type Key = u64;
+
 #[allow(dead_code)]
 pub fn pretty_bb(bb: Bitboard) -> String {
     let mut output = String::new();
@@ -302,7 +345,7 @@ macro_rules! foreach_bb {
     ($board:expr, $sq:ident, $e:expr) => {
         let mut bb = $board;
         while bb != 0 {
-            let $sq = crate::types::get_pos(bb);
+            let $sq = $crate::types::get_pos(bb);
             $e;
             bb &= bb.wrapping_sub(1);
         }
@@ -312,6 +355,9 @@ macro_rules! foreach_bb {
 /// Move.
 pub type Move = u32;
 
+/// Invalid move.
+pub const MOVE_NONE: Move = 0;
+
 /// Type of the move.
 #[derive(FromPrimitive, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 #[repr(usize)]
@@ -329,18 +375,23 @@ pub enum MoveType {
 }
 
 /// Mask of a captured piece.
+#[allow(clippy::unusual_byte_groupings)]
 const MOVE_CAP: u32 = 0b1111_0_000_000000_000000;
 const MOVE_CAP_SHIFT: u32 = 16;
 /// Mask of a demise flag.
+#[allow(clippy::unusual_byte_groupings)]
 pub const MOVE_DEMISE: u32 = 0b0000_1_000_000000_000000;
 /// Mask of a move type.
+#[allow(clippy::unusual_byte_groupings)]
 const MOVE_TYPE: u32 = 0b0000_0_111_000000_000000;
 const MOVE_TYPE_SHIFT: u32 = 12;
 /// Mask of a square the piece move from.
 /// Or the piece type if the move type is drop.
+#[allow(clippy::unusual_byte_groupings)]
 const MOVE_FROM: u32 = 0b0000_0_000_111111_000000;
 const MOVE_FROM_SHIFT: u32 = 6;
 /// Mask of a square the piece move to.
+#[allow(clippy::unusual_byte_groupings)]
 const MOVE_TO: u32 = 0b0000_0_000_000000_111111;
 const MOVE_TO_SHIFT: u32 = 0;
 
@@ -441,6 +492,20 @@ impl Eq for ExtMove {}
 pub type Value = i16;
 
 pub const VALUE_INF: i16 = 32001;
+pub const VALUE_DRAW: i16 = 0;
 pub const VALUE_WIN: i16 = 20000;
 
 pub const MAX_PLY: usize = 256;
+
+/// Values beyond this are wins found by the search.
+pub const VALUE_WIN_IN_MAX_PLY: Value = VALUE_WIN - MAX_PLY as Value;
+
+/// Value of winning at `ply` from the root.
+pub fn win_in(ply: usize) -> Value {
+    VALUE_WIN - ply as Value
+}
+
+/// Value of losing at `ply` from the root.
+pub fn lose_in(ply: usize) -> Value {
+    -VALUE_WIN + ply as Value
+}
@@ -0,0 +1,83 @@
+use std::sync::LazyLock;
+
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256StarStar;
+
+use crate::types::{count_hand, Hand, Key, PieceType, Side, PIECE_NB, SIDE_NB, SQUARE_NB};
+
+pub static ZOBRIST: LazyLock<Zobrist> = LazyLock::new(Zobrist::new);
+
+/// Piece types which can be in hand.
+pub const HAND_PIECE_TYPES: [PieceType; HAND_TYPE_NB] = [
+    PieceType::Light,
+    PieceType::Heavy,
+    PieceType::General,
+    PieceType::Knight,
+    PieceType::Arrow,
+    PieceType::Archer0,
+];
+/// Count of piece types which can be in hand.
+pub const HAND_TYPE_NB: usize = 6;
+/// Count of pieces of the same type in hand.
+pub const HAND_COUNT_NB: usize = 16;
+/// Count of states of demise.
+pub const DEMISE_NB: usize = 3;
+
+/// Random keys of Zobrist hashing.
+pub struct Zobrist {
+    /// Key of the piece at the square.
+    pub psq: [[Key; SQUARE_NB]; PIECE_NB],
+    /// Key of the count of the piece in hand.
+    pub hand: [[[Key; HAND_COUNT_NB]; HAND_TYPE_NB]; SIDE_NB],
+    /// Key of the count of demise.
+    pub demise: [[Key; DEMISE_NB]; SIDE_NB],
+    /// Key of the side to move.
+    pub side: Key,
+}
+
+impl Zobrist {
+    fn new() -> Self {
+        let mut rng = Xoshiro256StarStar::seed_from_u64(20240901);
+        let mut psq = [[0; SQUARE_NB]; PIECE_NB];
+        for keys in psq.iter_mut() {
+            for key in keys.iter_mut() {
+                *key = rng.gen();
+            }
+        }
+        let mut hand = [[[0; HAND_COUNT_NB]; HAND_TYPE_NB]; SIDE_NB];
+        for keys in hand.iter_mut() {
+            for keys in keys.iter_mut() {
+                // The key of an empty hand is zero.
+                for key in keys.iter_mut().skip(1) {
+                    *key = rng.gen();
+                }
+            }
+        }
+        let mut demise = [[0; DEMISE_NB]; SIDE_NB];
+        for keys in demise.iter_mut() {
+            for key in keys.iter_mut().skip(1) {
+                *key = rng.gen();
+            }
+        }
+        Zobrist {
+            psq,
+            hand,
+            demise,
+            side: rng.gen(),
+        }
+    }
+
+    /// Returns the key of the hand.
+    pub fn hand_key(&self, side: Side, hand: Hand) -> Key {
+        let mut key = 0;
+        for (i, pt) in HAND_PIECE_TYPES.into_iter().enumerate() {
+            key ^= self.hand[side as usize][i][count_hand(hand, pt) as usize];
+        }
+        key
+    }
+
+    /// Returns the key of the count of demise.
+    pub fn demise_key(&self, side: Side, demise: usize) -> Key {
+        self.demise[side as usize][demise.min(DEMISE_NB - 1)]
+    }
+}
@@ -0,0 +1,20 @@
+#![no_main]
+
+use std::str::FromStr;
+
+use alex::position::Position;
+use libfuzzer_sys::fuzz_target;
+
+// Parsed positions must be written back in a form which is parsed to the same position.
+fuzz_target!(|data: &[u8]| {
+    let Ok(mfen) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(position) = Position::from_str(mfen) else {
+        return;
+    };
+    let written = position.to_string();
+    let reparsed = Position::from_str(&written).unwrap();
+    assert_eq!(reparsed.to_string(), written);
+    assert_eq!(reparsed.key(), position.key());
+});
@@ -0,0 +1,30 @@
+#![no_main]
+
+use std::str::FromStr;
+
+use alex::position::Position;
+use libfuzzer_sys::fuzz_target;
+
+// The first line is the MFEN and the rest are moves, and the legal ones are played.
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let (mfen, moves) = input.split_once('\n').unwrap_or((input, ""));
+    let mut position = Position::from_str(mfen).unwrap_or_else(|_| {
+        Position::from_str("bngkpgnb/llhhhhll/8/8/8/8/LLHHHHLL/BNGPKGNB b - 0 0").unwrap()
+    });
+    let mut played = Vec::new();
+    for mv in moves.split_whitespace() {
+        let Ok(mv) = position.read_move(mv.to_string()) else {
+            continue;
+        };
+        position.do_move(mv, None);
+        assert_eq!(position.key(), position.compute_key());
+        played.push(mv);
+    }
+    for &mv in played.iter().rev() {
+        position.undo_move(mv);
+    }
+    assert_eq!(position.key(), position.compute_key());
+});